@@ -1,4 +1,4 @@
-use criterion::{criterion_group, Bencher, Criterion, Fun};
+use criterion::{criterion_group, BenchmarkId, Criterion};
 use multiversion::multiversion;
 use rand::distributions::Standard;
 use rand::Rng;
@@ -29,17 +29,16 @@ fn bench(c: &mut Criterion) {
             .sample_iter(&Standard)
             .take(*i)
             .collect::<Vec<_>>();
-        let functions = vec![
-            Fun::new("direct", |b: &mut Bencher, i: &Vec<f32>| {
-                let mut o = vec![0f32; i.len()];
-                b.iter(|| direct(i.as_slice(), o.as_mut_slice(), 2.0))
-            }),
-            Fun::new("generic", |b: &mut Bencher, i: &Vec<f32>| {
-                let mut o = vec![0f32; i.len()];
-                b.iter(|| generic(i.as_slice(), o.as_mut_slice(), 2.0))
-            }),
-        ];
-        c.bench_functions(&format!("{} values", i), functions, input);
+        let mut group = c.benchmark_group(format!("{} values", i));
+        group.bench_with_input(BenchmarkId::new("direct", i), &input, |b, i| {
+            let mut o = vec![0f32; i.len()];
+            b.iter(|| direct(i.as_slice(), o.as_mut_slice(), 2.0))
+        });
+        group.bench_with_input(BenchmarkId::new("generic", i), &input, |b, i| {
+            let mut o = vec![0f32; i.len()];
+            b.iter(|| generic(i.as_slice(), o.as_mut_slice(), 2.0))
+        });
+        group.finish();
     }
 }
 
@@ -1,15 +1,8 @@
-use criterion::{criterion_group, Bencher, Criterion, Fun};
-use multiversion::{multiversion, target};
+use criterion::{criterion_group, BenchmarkId, Criterion};
+use multiversion::{bench::bench_all_versions, multiversion};
 use rand::distributions::Standard;
 use rand::Rng;
 
-#[target("[x86|x86_64]+avx")]
-unsafe fn square_avx(i: &[f32], o: &mut [f32]) {
-    for (i, o) in i.iter().zip(o) {
-        *o = i * i;
-    }
-}
-
 fn square_generic(i: &[f32], o: &mut [f32]) {
     for (i, o) in i.iter().zip(o) {
         *o = i * i;
@@ -30,22 +23,27 @@ pub fn bench(c: &mut Criterion) {
             .sample_iter(&Standard)
             .take(*i)
             .collect::<Vec<_>>();
-        let functions = vec![
-            Fun::new("generic", |b: &mut Bencher, i: &Vec<f32>| {
-                let mut o = vec![0f32; i.len()];
-                b.iter(|| square_generic(i.as_slice(), o.as_mut_slice()))
-            }),
-            Fun::new("AVX (direct)", |b: &mut Bencher, i: &Vec<f32>| {
-                let mut o = vec![0f32; i.len()];
-                assert!(is_x86_feature_detected!("avx"));
-                b.iter(|| unsafe { square_avx(i.as_slice(), o.as_mut_slice()) })
-            }),
-            Fun::new("AVX (via FMV)", |b: &mut Bencher, i: &Vec<f32>| {
+        let mut group = c.benchmark_group(format!("square {} values", i));
+        group.bench_with_input(BenchmarkId::new("generic", i), &input, |b, i| {
+            let mut o = vec![0f32; i.len()];
+            b.iter(|| square_generic(i.as_slice(), o.as_mut_slice()))
+        });
+        group.bench_with_input(BenchmarkId::new("AVX (via FMV)", i), &input, |b, i| {
+            let mut o = vec![0f32; i.len()];
+            b.iter(|| square_fmv(i.as_slice(), o.as_mut_slice()))
+        });
+        group.finish();
+
+        bench_all_versions(
+            c,
+            &format!("square {} values (each version)", i),
+            SQUARE_FMV_VERSIONS,
+            &input,
+            |b, f, i| {
                 let mut o = vec![0f32; i.len()];
-                b.iter(|| square_fmv(i.as_slice(), o.as_mut_slice()))
-            }),
-        ];
-        c.bench_functions(&format!("square {} values", i), functions, input);
+                b.iter(|| unsafe { f(i.as_slice(), o.as_mut_slice()) })
+            },
+        );
     }
 }
 
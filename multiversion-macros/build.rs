@@ -0,0 +1,20 @@
+use std::env;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-check-cfg=cfg(unsafe_extern_blocks)");
+
+    // Extern blocks must be unsafe in the 2024 edition, but can't be before Rust 1.82.  Proc macros
+    // are built by the same compiler as the crates that use them.
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let minor = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|version| version.split('.').nth(1)?.parse::<u32>().ok());
+    if minor.map_or(true, |minor| minor >= 82) {
+        println!("cargo:rustc-cfg=unsafe_extern_blocks");
+    }
+}
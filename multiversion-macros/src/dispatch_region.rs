@@ -0,0 +1,139 @@
+use crate::meta::{parse_attributes, parse_crate_path};
+use crate::multiversion::parse_target;
+use crate::static_dispatch::process_static_dispatch;
+use crate::target::{check_target_order, Target};
+use crate::target_cfg::process_target_cfg;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_quote,
+    spanned::Spanned,
+    Attribute, Block, Error, Meta, NestedMeta, Path, Result,
+};
+
+pub(crate) struct DispatchRegion {
+    attrs: Vec<Attribute>,
+    block: Block,
+}
+
+impl Parse for DispatchRegion {
+    fn parse(input: ParseStream) -> Result<Self> {
+        Ok(Self {
+            attrs: input.call(Attribute::parse_outer)?,
+            block: input.parse()?,
+        })
+    }
+}
+
+// Creates a copy of the block for the target, as the body of a closure
+fn make_closure(target: Option<&Target>, block: &Block, crate_path: &Path) -> Result<TokenStream> {
+    let mut block = block.clone();
+    process_target_cfg(target.cloned(), &mut block)?;
+    process_static_dispatch(&mut block, target, crate_path)?;
+    Ok(quote! { || #block })
+}
+
+pub(crate) fn make_dispatch_region(region: DispatchRegion) -> Result<TokenStream> {
+    let mut targets = Vec::new();
+    let mut crate_path = parse_quote!(multiversion);
+    let unused = parse_attributes(region.attrs.into_iter(), |path, nested| {
+        Ok(match path.to_string().as_str() {
+            "crate_path" => {
+                crate_path = parse_crate_path(nested)?;
+                true
+            }
+            "clone" => {
+                meta_parser! {
+                    nested => [
+                        "target" => target,
+                        "cpu" => cpu,
+                    ]
+                }
+                targets.push(parse_target(nested, target, cpu)?);
+                true
+            }
+            _ => false,
+        })
+    })?;
+    if let Some(attr) = unused.first() {
+        return Err(Error::new(attr.span(), "expected `clone` or `crate_path`"));
+    }
+    check_target_order(&targets, |target| target)?;
+
+    // Each target runs its copy of the block within a function that enables the target's features.
+    // The block is passed as a closure, which is always inlined into the function, so it's compiled
+    // with the target's features.  The closure is created outside of the unsafe call, so the block
+    // isn't an unsafe context.
+    let mut trampolines = Vec::new();
+    let mut selectors = Vec::new();
+    let mut arms = Vec::new();
+    for (index, target) in targets.iter().enumerate() {
+        let target_arch = target.target_arch();
+        let target_feature = target.target_feature();
+        let features_detected = target.features_detected(&crate_path);
+        let trampoline = format_ident!("__multiversion_region_{}", index);
+        let closure = make_closure(Some(target), &region.block, &crate_path)?;
+        trampolines.push(quote! {
+            #target_arch
+            #(#target_feature)*
+            #[inline]
+            unsafe fn #trampoline<R>(f: impl FnOnce() -> R) -> R {
+                f()
+            }
+        });
+        selectors.push(quote! {
+            #target_arch
+            {
+                if #features_detected {
+                    return #index;
+                }
+            }
+        });
+        arms.push(quote! {
+            #target_arch
+            #index => {
+                let __run = |f| unsafe { #trampoline(f) };
+                __run(#[inline(always)] #closure)
+            }
+        });
+    }
+    let default = make_closure(None, &region.block, &crate_path)?;
+    let default_index = targets.len();
+
+    // With runtime detection, the selected target is cached, like the dispatcher of a function
+    let select = if cfg!(feature = "std") {
+        quote! {
+            static __DISPATCHED_INDEX: #crate_path::__private::Dispatched =
+                #crate_path::__private::Dispatched::new_index();
+            let __index = match __DISPATCHED_INDEX.load_index() {
+                Some(index) => index,
+                None => {
+                    let index = __multiversion_select();
+                    __DISPATCHED_INDEX.store_index(index);
+                    index
+                }
+            };
+        }
+    } else {
+        quote! {
+            let __index = __multiversion_select();
+        }
+    };
+
+    Ok(quote! {
+        {
+            #(#trampolines)*
+            #[cold]
+            fn __multiversion_select() -> usize {
+                #(#selectors)*
+                #default_index
+            }
+            #select
+            match __index {
+                #(#arms)*
+                _ => (#default)(),
+            }
+        }
+    })
+}
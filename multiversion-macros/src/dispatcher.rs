@@ -4,7 +4,9 @@ use crate::{
 };
 use proc_macro2::{Span, TokenStream};
 use quote::{quote, ToTokens};
-use syn::{parse_quote, Attribute, Block, Ident, ItemFn, Path, Result, Signature, Visibility};
+use syn::{
+    parse_quote, Attribute, Block, Error, Ident, ItemFn, Path, Result, Signature, Visibility,
+};
 
 pub(crate) fn feature_fn_name(ident: &Ident, target: Option<&Target>) -> (Ident, Ident) {
     if let Some(target) = target {
@@ -22,6 +24,25 @@ pub(crate) fn feature_fn_name(ident: &Ident, target: Option<&Target>) -> (Ident,
     (default.clone(), default)
 }
 
+pub(crate) fn static_dispatch_fn_name(ident: &Ident) -> Ident {
+    Ident::new(&format!("__{}_static_dispatch", ident), ident.span())
+}
+
+fn dispatched_index_fn_name(ident: &Ident) -> Ident {
+    Ident::new(&format!("__{}_dispatched_index", ident), ident.span())
+}
+
+fn ifunc_index_static_name(ident: &Ident) -> Ident {
+    Ident::new(
+        &format!(
+            "__{}_IFUNC_INDEX",
+            ident.to_string().trim_start_matches("r#")
+        )
+        .to_uppercase(),
+        ident.span(),
+    )
+}
+
 pub(crate) struct Specialization {
     pub target: Target,
     pub block: Block,
@@ -34,14 +55,19 @@ impl Specialization {
         vis: &Visibility,
         sig: &Signature,
         attrs: &[Attribute],
-        associated: bool,
+        maybe_self: &TokenStream,
+        crate_path: &Path,
+        version_cfg: Option<&Attribute>,
     ) -> Result<Vec<ItemFn>> {
         let (fn_name, dispatch_fn_name) = feature_fn_name(&sig.ident, Some(&self.target));
 
         let mut target_attrs = Vec::new();
-        target_attrs.push(parse_quote! { #[inline] });
+        if !attrs.iter().any(util::is_inline_attr) {
+            target_attrs.push(parse_quote! { #[inline] });
+        }
         target_attrs.push(parse_quote! { #[doc(hidden)] });
         target_attrs.extend(attrs.iter().cloned());
+        target_attrs.extend(version_cfg.cloned());
 
         // If this target doesn't have any features, treat it as a default version
         if self.target.has_features_specified() {
@@ -51,7 +77,7 @@ impl Specialization {
             }
 
             // create unsafe/target fn
-            let fn_params = crate::util::fn_params(&sig);
+            let fn_params = crate::util::fn_params(sig);
             let maybe_await = sig.asyncness.map(|_| util::await_tokens());
             let unsafe_sig = Signature {
                 ident: fn_name,
@@ -76,17 +102,14 @@ impl Specialization {
                 ..outer_sig
             };
             let target_fn_ident = &target_fn.sig.ident;
-            let maybe_self = if associated {
-                quote! { Self:: }
-            } else {
-                Default::default()
-            };
+            let mut dispatch_attrs = vec![
+                parse_quote! { #[inline(always)] },
+                parse_quote! { #[doc(hidden)] },
+                self.target.target_arch(),
+            ];
+            dispatch_attrs.extend(version_cfg.cloned());
             let dispatch_fn = ItemFn {
-                attrs: vec![
-                    parse_quote! { #[inline(always)] },
-                    parse_quote! { #[doc(hidden)] },
-                    self.target.target_arch(),
-                ],
+                attrs: dispatch_attrs,
                 vis: vis.clone(),
                 block: Box::new(parse_quote! {
                     {
@@ -96,7 +119,12 @@ impl Specialization {
                 sig: outer_sig,
             };
             let mut fns = vec![dispatch_fn];
-            fns.extend(make_target_fn_items(Some(&self.target), target_fn)?);
+            fns.extend(make_target_fn_items(
+                Some(&self.target),
+                target_fn,
+                crate_path,
+                Some(maybe_self),
+            )?);
             Ok(fns)
         } else {
             make_target_fn_items(
@@ -110,6 +138,8 @@ impl Specialization {
                     },
                     block: Box::new(self.block.clone()),
                 },
+                crate_path,
+                Some(maybe_self),
             )
         }
     }
@@ -122,10 +152,33 @@ pub(crate) struct Dispatcher {
     pub specializations: Vec<Specialization>,
     pub default: Block,
     pub associated: bool,
+    pub concrete_self: bool,
+    pub versions_trait: Option<Path>,
+    pub ifunc: bool,
     pub crate_path: Path,
 }
 
+// Returns the parts of a unique symbol name for an ifunc, to be concatenated with `concat!`.
+// Symbols are global, so the name includes the module path of the function.
+fn ifunc_symbol(ident: &Ident) -> TokenStream {
+    let name = format!(
+        "::{}::__multiversion_ifunc",
+        ident.to_string().trim_start_matches("r#")
+    );
+    quote! { module_path!(), #name }
+}
+
 impl Dispatcher {
+    // Returns the path prefix of the versions, which are in a separate trait for trait
+    // implementations, since a trait implementation can only contain members of the trait
+    fn maybe_self(&self) -> TokenStream {
+        match &self.versions_trait {
+            Some(versions_trait) => quote! { <Self as #versions_trait>:: },
+            None if self.associated => quote! { Self:: },
+            None => TokenStream::new(),
+        }
+    }
+
     // Create an attribute that disables an expression if we're on an architecture with a
     // specialized default
     fn cfg_if_not_defaulted(&self) -> Attribute {
@@ -146,18 +199,149 @@ impl Dispatcher {
         parse_quote! { #[cfg(not(any(#(target_arch = #defaulted_arches),*)))] }
     }
 
+    // Create the condition under which the specialization at `index` (or the default version, at
+    // the end) can never be selected, because an earlier specialization's features are enabled
+    // during compilation.  Specializations without features replace the default version, so are
+    // shadowed by any specialization with features.
+    fn shadowed(&self, index: usize) -> Option<TokenStream> {
+        let end = match self.specializations.get(index) {
+            Some(Specialization { target, .. }) if target.has_features_specified() => index,
+            _ => self.specializations.len(),
+        };
+        let enabled = self.specializations[..end]
+            .iter()
+            .filter_map(|Specialization { target, .. }| target.features_enabled())
+            .collect::<Vec<_>>();
+        if enabled.is_empty() {
+            None
+        } else {
+            Some(quote! { any(#(#enabled),*) })
+        }
+    }
+
+    // Create an attribute that disables an expression for a version that can never be selected
+    fn cfg_if_selectable(&self, index: usize) -> Option<Attribute> {
+        self.shadowed(index)
+            .map(|shadowed| parse_quote! { #[cfg(not(#shadowed))] })
+    }
+
+    // Create an attribute for the items of the version at `index`.  Versions are always compiled,
+    // so they can still be called by name and tested when they can't be selected, but may be
+    // unused.
+    fn version_cfg(&self, index: usize) -> Option<Attribute> {
+        self.shadowed(index)
+            .map(|shadowed| parse_quote! { #[cfg_attr(#shadowed, allow(dead_code))] })
+    }
+
+    // Create the condition under which the testing module allows dispatching a version with
+    // features enabled during compilation, or `None` if the testing module isn't available
+    fn testing_allows(&self, target: &Target) -> Option<TokenStream> {
+        if cfg!(feature = "std") {
+            let crate_path = &self.crate_path;
+            let features = target.features();
+            Some(quote! { #crate_path::__private::testing_allows(&[#(#features),*]) })
+        } else {
+            None
+        }
+    }
+
+    // Create statements that return the first selected version.  Versions with features are
+    // selected if `detected`, or unconditionally if their features are enabled during compilation,
+    // in which case later versions aren't selected.  Otherwise, the `default` statements, which
+    // must return, are run.  If `overridable`, versions with features enabled during compilation
+    // aren't selected while the testing module disallows them, so the default version is selected.
+    fn select_version(
+        &self,
+        overridable: bool,
+        detected: impl Fn(usize, &Target) -> TokenStream,
+        selected: impl Fn(usize, &Target) -> TokenStream,
+        default: TokenStream,
+    ) -> TokenStream {
+        let return_if_selected = self
+            .specializations
+            .iter()
+            .enumerate()
+            .filter(|(_, Specialization { target, .. })| target.has_features_specified())
+            .map(|(index, Specialization { target, .. })| {
+                let target_arch = target.target_arch();
+                let selectable = self.cfg_if_selectable(index);
+                let detected = detected(index, target);
+                let selected = selected(index, target);
+                match target.features_enabled() {
+                    Some(enabled) => {
+                        let return_enabled = match self.testing_allows(target) {
+                            Some(allowed) if overridable => quote! {
+                                if #allowed {
+                                    return #selected;
+                                }
+                            },
+                            _ => quote! { return #selected; },
+                        };
+                        quote! {
+                            #target_arch
+                            #selectable
+                            {
+                                #[cfg(#enabled)]
+                                #return_enabled
+                                #[cfg(not(#enabled))]
+                                {
+                                    if #detected {
+                                        return #selected;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    None => quote! {
+                        #target_arch
+                        #selectable
+                        {
+                            if #detected {
+                                return #selected;
+                            }
+                        }
+                    },
+                }
+            });
+        let selectable = if overridable && cfg!(feature = "std") {
+            None
+        } else {
+            self.cfg_if_selectable(self.specializations.len())
+        };
+        quote! {
+            #(#return_if_selected)*
+            #selectable
+            {
+                #default
+            }
+        }
+    }
+
     // Create specialized functions for arch/feature sets
     fn feature_fns(&self) -> Result<Vec<ItemFn>> {
         let mut fns = Vec::new();
-        for f in &self.specializations {
-            fns.extend(f.make_fn(&self.vis, &self.sig, &self.attrs, self.associated)?);
+        for (index, f) in self.specializations.iter().enumerate() {
+            fns.extend(f.make_fn(
+                &self.vis,
+                &self.sig,
+                &self.attrs,
+                &self.maybe_self(),
+                &self.crate_path,
+                self.version_cfg(index).as_ref(),
+            )?);
         }
 
         // Create default fn
-        let mut attrs = self.attrs.clone();
+        let mut attrs = self
+            .attrs
+            .iter()
+            .filter(|attr| !util::is_inline_attr(attr))
+            .cloned()
+            .collect::<Vec<_>>();
         attrs.push(parse_quote! { #[inline(always)] });
         attrs.push(parse_quote! { #[doc(hidden)] });
         attrs.push(self.cfg_if_not_defaulted());
+        attrs.extend(self.version_cfg(self.specializations.len()));
         fns.extend(make_target_fn_items(
             None,
             ItemFn {
@@ -169,133 +353,705 @@ impl Dispatcher {
                 },
                 block: Box::new(self.default.clone()),
             },
+            &self.crate_path,
+            Some(&self.maybe_self()),
         )?);
 
         Ok(fns)
     }
 
-    fn dispatcher_fn(&self) -> Result<ItemFn> {
+    // Create a function that statically dispatches the best version supported by the caller
+    fn static_dispatch_fn(&self) -> ItemFn {
         let fn_params = util::fn_params(&self.sig);
         let (normalized_signature, argument_names) = util::normalize_signature(&self.sig);
-        let block: Block = if cfg!(feature = "std")
-            && fn_params.is_empty()
+        let maybe_await = self.sig.asyncness.map(|_| util::await_tokens());
+        let maybe_self = self.maybe_self();
+        let crate_path = &self.crate_path;
+        let caller = Ident::new("__multiversion_caller", Span::call_site());
+        let default_fn = feature_fn_name(&self.sig.ident, None).1;
+        let select_version = self.select_version(
+            false,
+            |_, target| {
+                let features = target.features();
+                quote! {
+                    {
+                        struct __MultiversionVersion;
+                        impl #crate_path::__private::TargetFeatures for __MultiversionVersion {
+                            const FEATURES: &'static [&'static str] = &[#(#features),*];
+                        }
+                        #crate_path::__private::supports::<_, __MultiversionVersion>(&#caller)
+                    }
+                }
+            },
+            |_, target| {
+                let function = feature_fn_name(&self.sig.ident, Some(target)).0;
+                quote! {
+                    unsafe { #maybe_self#function::<#(#fn_params),*>(#(#argument_names),*)#maybe_await }
+                }
+            },
+            quote! {
+                return #maybe_self#default_fn::<#(#fn_params),*>(#(#argument_names),*)#maybe_await;
+            },
+        );
+        let mut sig = Signature {
+            ident: static_dispatch_fn_name(&self.sig.ident),
+            ..normalized_signature
+        };
+        sig.inputs
+            .push(parse_quote! { #caller: impl #crate_path::__private::TargetFeatures });
+        ItemFn {
+            attrs: vec![
+                parse_quote! { #[inline(always)] },
+                parse_quote! { #[doc(hidden)] },
+            ],
+            vis: self.vis.clone(),
+            sig,
+            block: Box::new(parse_quote! {
+                {
+                    #select_version
+                }
+            }),
+        }
+    }
+
+    fn companion_fn_name(&self, suffix: &str) -> Ident {
+        Ident::new(
+            &format!("{}_{}", self.sig.ident, suffix),
+            self.sig.ident.span(),
+        )
+    }
+
+    // Create a description of a version of the function
+    fn version(&self, index: usize, target: Option<&Target>) -> TokenStream {
+        let crate_path = &self.crate_path;
+        let specification = target.map_or("default", Target::specification);
+        let default = target.is_none();
+        let cpu = match target.and_then(Target::cpu) {
+            Some(cpu) => quote! { Some(#cpu) },
+            None => quote! { None },
+        };
+        let features = target.into_iter().flat_map(Target::features);
+        let supported = match target {
+            // Versions with features that are never selected aren't supported, since the
+            // dispatcher can't select them while testing
+            Some(target) if target.has_features_specified() => {
+                let target_arch = target.target_arch();
+                let selectable = self.cfg_if_selectable(index);
+                let features = target.features();
+                quote! {
+                    || {
+                        #target_arch
+                        #selectable
+                        {
+                            if #crate_path::are_cpu_features_detected!(#(#features),*) {
+                                return true;
+                            }
+                        }
+                        false
+                    }
+                }
+            }
+            // Versions without features are always compiled, so are supported on their
+            // architectures, unless replaced by a specialization without features
+            Some(target) => {
+                let arches = target.arches();
+                quote! { || cfg!(any(#(target_arch = #arches),*)) }
+            }
+            None => {
+                let arches = self
+                    .specializations
+                    .iter()
+                    .filter(|Specialization { target, .. }| !target.has_features_specified())
+                    .flat_map(|Specialization { target, .. }| target.arches());
+                quote! { || cfg!(not(any(#(target_arch = #arches),*))) }
+            }
+        };
+        quote! {
+            #crate_path::Version::__new(#index, #specification, #cpu, &[#(#features),*], #default, #supported)
+        }
+    }
+
+    // Create a function that returns descriptions of every version of the function
+    fn versions_fn(&self) -> ItemFn {
+        let vis = &self.vis;
+        let crate_path = &self.crate_path;
+        let versions = self
+            .specializations
+            .iter()
+            .enumerate()
+            .map(|(index, Specialization { target, .. })| self.version(index, Some(target)))
+            .chain(std::iter::once(
+                self.version(self.specializations.len(), None),
+            ));
+        let ident = self.companion_fn_name("versions");
+        let doc = format!(
+            "Returns every version of `{}`, in the order they are selected by feature detection.",
+            self.sig.ident
+        );
+        parse_quote! {
+            #[doc = #doc]
+            #[doc(hidden)]
+            #vis fn #ident() -> &'static [#crate_path::Version] {
+                static VERSIONS: &[#crate_path::Version] = &[#(#versions),*];
+                VERSIONS
+            }
+        }
+    }
+
+    // Create statements that return the index of the version selected by feature detection.
+    // Targets without features replace the default version on their architectures.  If
+    // `overridable`, the testing module can select the default version instead of versions with
+    // features enabled during compilation.
+    fn select_index(
+        &self,
+        overridable: bool,
+        detected: impl Fn(&Target) -> TokenStream,
+    ) -> TokenStream {
+        let return_if_defaulted = self.specializations.iter().enumerate().filter_map(
+            |(index, Specialization { target, .. })| {
+                if target.has_features_specified() {
+                    None
+                } else {
+                    let target_arch = target.target_arch();
+                    Some(quote! {
+                        #target_arch
+                        {
+                            return #index;
+                        }
+                    })
+                }
+            },
+        );
+        let default = self.specializations.len();
+        self.select_version(
+            overridable,
+            |_, target| detected(target),
+            |index, _| quote! { #index },
+            quote! {
+                #(#return_if_defaulted)*
+                return #default;
+            },
+        )
+    }
+
+    // Create a function that returns the index of the version selected by the dispatcher, which is
+    // cached when runtime dispatching is enabled.  The cache is shared by every instantiation of
+    // the function, since statics can't use generic parameters or `Self`.  Indirect functions
+    // store the index selected by their resolver instead.
+    fn dispatched_index_fn(&self) -> ItemFn {
+        let crate_path = &self.crate_path;
+        let ident = dispatched_index_fn_name(&self.sig.ident);
+        let select_index = self.select_index(true, |target| target.features_detected(crate_path));
+        let block: Block = if cfg!(feature = "std") {
+            parse_quote! {
+                {
+                    #[cold]
+                    #[allow(unreachable_code)]
+                    fn __multiversion_select() -> usize {
+                        #select_index
+                    }
+                    static __DISPATCHED_INDEX: #crate_path::__private::Dispatched =
+                        #crate_path::__private::Dispatched::new_index();
+                    match __DISPATCHED_INDEX.load_index() {
+                        Some(index) => index,
+                        None => {
+                            let index = __multiversion_select();
+                            __DISPATCHED_INDEX.store_index(index);
+                            index
+                        }
+                    }
+                }
+            }
+        } else {
+            parse_quote! {
+                {
+                    #select_index
+                }
+            }
+        };
+        let block: Block = match self.ifunc_cfg() {
+            Some(ifunc_cfg) => {
+                let ifunc_index = ifunc_index_static_name(&self.sig.ident);
+                parse_quote! {
+                    {
+                        #[cfg(#ifunc_cfg)]
+                        return #ifunc_index.load(core::sync::atomic::Ordering::Relaxed);
+                        #[cfg(not(#ifunc_cfg))]
+                        #block
+                    }
+                }
+            }
+            None => block,
+        };
+        parse_quote! {
+            #[doc(hidden)]
+            #[inline(always)]
+            #[allow(unreachable_code)]
+            fn #ident() -> usize #block
+        }
+    }
+
+    // Create a function that returns the version selected by the dispatcher
+    fn selected_version_fn(&self) -> ItemFn {
+        let vis = &self.vis;
+        let crate_path = &self.crate_path;
+        let maybe_self = self.maybe_self();
+        let versions_fn = self.companion_fn_name("versions");
+        let dispatched_index = dispatched_index_fn_name(&self.sig.ident);
+        let ident = self.companion_fn_name("selected_version");
+        let doc = format!(
+            "Returns the version of `{}` selected by feature detection.",
+            self.sig.ident
+        );
+        parse_quote! {
+            #[doc = #doc]
+            #[doc(hidden)]
+            #vis fn #ident() -> #crate_path::Version {
+                #maybe_self#versions_fn()[#maybe_self#dispatched_index()]
+            }
+        }
+    }
+
+    // Create a constant containing every version of the function along with a pointer to it.
+    // Function pointers can only be taken for functions that aren't generic, async or impl Trait.
+    // Associated functions are skipped, since the implementation may be generic.
+    fn version_fns_const(&self) -> Option<TokenStream> {
+        if !util::fn_params(&self.sig).is_empty()
+            || self.sig.asyncness.is_some()
+            || util::impl_trait_present(&self.sig)
+            || self.associated
+        {
+            return None;
+        }
+
+        let vis = &self.vis;
+        let crate_path = &self.crate_path;
+        let mut fn_ty = util::fn_type_from_signature(&self.sig);
+        fn_ty.unsafety = parse_quote! { unsafe };
+        let ident = Ident::new(
+            &format!("{}_VERSIONS", self.sig.ident).to_uppercase(),
+            self.sig.ident.span(),
+        );
+        let doc = format!(
+            "Every version of `{}` available on this architecture, with a pointer to each version.",
+            self.sig.ident
+        );
+        let default_fn = feature_fn_name(&self.sig.ident, None).1;
+
+        // Versions for other architectures don't exist, so each architecture gets its own table
+        let mut arches = Vec::new();
+        for Specialization { target, .. } in &self.specializations {
+            for arch in target.arches() {
+                if !arches.contains(&arch) {
+                    arches.push(arch);
+                }
+            }
+        }
+        let entry = |index: usize, target: Option<&Target>, function: &Ident| {
+            let version = self.version(index, target);
+            quote! { (#version, #function as #fn_ty) }
+        };
+        let mut tables = arches
+            .iter()
+            .map(|arch| {
+                let mut entries = Vec::new();
+                let mut default = None;
+                for (index, Specialization { target, .. }) in
+                    self.specializations.iter().enumerate()
+                {
+                    if !target.arches().any(|a| a == *arch) {
+                        continue;
+                    }
+                    if target.has_features_specified() {
+                        let function = feature_fn_name(&self.sig.ident, Some(target)).0;
+                        entries.push(entry(index, Some(target), &function));
+                    } else if default.is_none() {
+                        default = Some(entry(index, Some(target), &default_fn));
+                    }
+                }
+                entries.push(
+                    default.unwrap_or_else(|| entry(self.specializations.len(), None, &default_fn)),
+                );
+                quote! {
+                    #[cfg(target_arch = #arch)]
+                    #[doc = #doc]
+                    #[doc(hidden)]
+                    #vis const #ident: &'static [(#crate_path::Version, #fn_ty)] = &[#(#entries),*];
+                }
+            })
+            .collect::<Vec<_>>();
+        let default = entry(self.specializations.len(), None, &default_fn);
+        tables.push(quote! {
+            #[cfg(not(any(#(target_arch = #arches),*)))]
+            #[doc = #doc]
+            #[doc(hidden)]
+            #vis const #ident: &'static [(#crate_path::Version, #fn_ty)] = &[#default];
+        });
+        Some(quote! { #(#tables)* })
+    }
+
+    // Create a dispatcher that calls the version at the cached index
+    fn index_dispatch_block(&self) -> Block {
+        let fn_params = util::fn_params(&self.sig);
+        let (_, argument_names) = util::normalize_signature(&self.sig);
+        let maybe_await = self.sig.asyncness.map(|_| util::await_tokens());
+        let maybe_self = self.maybe_self();
+        let default_fn = feature_fn_name(&self.sig.ident, None).1;
+        let dispatched_index = dispatched_index_fn_name(&self.sig.ident);
+        let call_selected = self.select_version(
+            true,
+            |index, _| quote! { __index == #index },
+            |_, target| {
+                let function = feature_fn_name(&self.sig.ident, Some(target)).1;
+                quote! { #maybe_self#function::<#(#fn_params),*>(#(#argument_names),*)#maybe_await }
+            },
+            quote! {
+                return #maybe_self#default_fn::<#(#fn_params),*>(#(#argument_names),*)#maybe_await;
+            },
+        );
+        parse_quote! {
+            {
+                let __index = #maybe_self#dispatched_index();
+                #call_selected
+            }
+        }
+    }
+
+    // Returns true if the selected function is cached in an atomic function pointer, which is
+    // possible if the function is not generic, async, or associated with a generic implementation,
+    // and does not take or return an impl trait
+    fn pointer_dispatch(&self) -> bool {
+        util::fn_params(&self.sig).is_empty()
             && self.sig.asyncness.is_none()
             && !util::impl_trait_present(&self.sig)
-            && !self.associated
+            && (!self.associated || self.concrete_self)
+    }
+
+    // Create the conditions under which each version with features enabled during compilation is
+    // always selected, because no earlier version can be selected on the architecture
+    fn direct_conditions(&self) -> Vec<(&Target, TokenStream)> {
+        let mut earlier_arches = Vec::new();
+        let mut conditions = Vec::new();
+        for Specialization { target, .. } in &self.specializations {
+            if !target.has_features_specified() {
+                continue;
+            }
+            if let Some(enabled) = target.features_enabled() {
+                conditions.push((
+                    target,
+                    quote! { all(#enabled, not(any(#(target_arch = #earlier_arches),*))) },
+                ));
+            }
+            earlier_arches.extend(target.arches());
+        }
+        conditions
+    }
+
+    // Create the condition under which the function is dispatched with an indirect function, or
+    // `None` if it never is
+    fn ifunc_cfg(&self) -> Option<TokenStream> {
+        // Indirect functions can't read the environment variables of the `env-override` feature
+        if !self.ifunc
+            || !cfg!(feature = "std")
+            || cfg!(feature = "env-override")
+            || !self.pointer_dispatch()
         {
+            return None;
+        }
+        let direct = self
+            .direct_conditions()
+            .into_iter()
+            .map(|(_, condition)| condition);
+        Some(quote! {
+            all(
+                target_os = "linux",
+                target_env = "gnu",
+                any(target_arch = "x86", target_arch = "x86_64"),
+                not(any(#(#direct),*))
+            )
+        })
+    }
+
+    // Create match arms that map the index of each version with features to a pointer to it
+    fn index_arms(&self, maybe_self: &TokenStream) -> Vec<TokenStream> {
+        self.specializations
+            .iter()
+            .enumerate()
+            .filter(|(_, Specialization { target, .. })| target.has_features_specified())
+            .map(|(index, Specialization { target, .. })| {
+                let target_arch = target.target_arch();
+                let selectable = self.cfg_if_selectable(index);
+                let function = feature_fn_name(&self.sig.ident, Some(target)).1;
+                quote! {
+                    #target_arch
+                    #selectable
+                    #index => #maybe_self#function,
+                }
+            })
+            .collect()
+    }
+
+    fn dispatcher_fn(&self) -> Result<TokenStream> {
+        let fn_params = util::fn_params(&self.sig);
+        let (normalized_signature, argument_names) = util::normalize_signature(&self.sig);
+        let crate_path = &self.crate_path;
+        let pointer_dispatch = self.pointer_dispatch();
+        if self.ifunc && !pointer_dispatch {
+            return Err(Error::new(
+                self.sig.ident.span(),
+                "`ifunc` requires a function that isn't generic, async, associated, or taking or returning `impl Trait`",
+            ));
+        }
+
+        let maybe_await = self.sig.asyncness.map(|_| util::await_tokens());
+        let maybe_self = self.maybe_self();
+        // If the features of a version are enabled during compilation, and no earlier version can be
+        // selected on the architecture, the version is always selected and called directly, unless
+        // the testing module disallows it
+        let default_fn = feature_fn_name(&self.sig.ident, None).1;
+        let direct_conditions = self.direct_conditions();
+        let return_direct = direct_conditions
+            .iter()
+            .map(|(target, condition)| {
+                let function = feature_fn_name(&self.sig.ident, Some(target)).1;
+                let call = quote! {
+                    #maybe_self#function::<#(#fn_params),*>(#(#argument_names),*)#maybe_await
+                };
+                match self.testing_allows(target) {
+                    Some(allowed) => quote! {
+                        #[cfg(#condition)]
+                        {
+                            if #allowed {
+                                return #call;
+                            }
+                            return #maybe_self#default_fn::<#(#fn_params),*>(#(#argument_names),*)#maybe_await;
+                        }
+                    },
+                    None => quote! {
+                        #[cfg(#condition)]
+                        return #call;
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+        let direct_conditions = direct_conditions.iter().map(|(_, condition)| condition);
+        let direct = quote! { any(#(#direct_conditions),*) };
+
+        let mut ifunc_items = TokenStream::new();
+        let block: Block = if cfg!(feature = "std") && pointer_dispatch {
             // Dispatching from an atomic fn pointer occurs when the following is true:
             //   * runtime-dispatching is enabled
             //   * the function is not generic
             //   * the function is not async
             //   * the function does not take or return an impl trait
-            //   * the function is not associated
-            let fn_ty = util::fn_type_from_signature(&self.sig)?;
-            let feature_detection = {
-                let return_if_detected =
-                    self.specializations
-                        .iter()
-                        .filter_map(|Specialization { target, .. }| {
-                            if target.has_features_specified() {
-                                let target_arch = target.target_arch();
-                                let features_detected = target.features_detected(&self.crate_path);
-                                let function = feature_fn_name(&self.sig.ident, Some(&target)).1;
-                                Some(quote! {
-                                    #target_arch
-                                    {
-                                        if #features_detected {
-                                            return #function
-                                        }
-                                    }
-                                })
-                            } else {
-                                None
-                            }
-                        });
-                let default_fn = feature_fn_name(&self.sig.ident, None).1;
-                quote! {
-                    fn __get_fn<#(#fn_params),*>() -> #fn_ty {
-                        #(#return_if_detected)*
-                        #default_fn
-                    };
-                }
-            };
+            //   * the function is not associated, or the implementation is not generic
+            let fn_ty = util::fn_type_from_signature(&self.sig);
+            let default_fn = feature_fn_name(&self.sig.ident, None).1;
+            let dispatched_index = dispatched_index_fn_name(&self.sig.ident);
+            let arms = self.index_arms(&maybe_self);
             let resolver_signature = Signature {
                 ident: Ident::new("__resolver_fn", Span::call_site()),
                 ..normalized_signature.clone()
             };
-            parse_quote! {
+            let atomic_block: Block = if self.associated {
+                // Nested functions can't use `Self`, so the function is resolved in the dispatcher
+                parse_quote! {
+                    {
+                        static __DISPATCHED_FN: #crate_path::__private::Dispatched =
+                            #crate_path::__private::Dispatched::new_unresolved();
+                        let __current_fn = match __DISPATCHED_FN.load_resolved() {
+                            Some(__current_ptr) => unsafe {
+                                core::mem::transmute::<*mut (), #fn_ty>(__current_ptr)
+                            },
+                            None => {
+                                let __current_fn: #fn_ty = match #maybe_self#dispatched_index() {
+                                    #(#arms)*
+                                    _ => #maybe_self#default_fn,
+                                };
+                                __DISPATCHED_FN.store(__current_fn as *mut ());
+                                __current_fn
+                            }
+                        };
+                        __current_fn(#(#argument_names),*)
+                    }
+                }
+            } else {
+                parse_quote! {
                 {
-                    use core::sync::atomic::{AtomicPtr, Ordering};
                     #[cold]
                     #resolver_signature {
-                        #feature_detection
-                        let __current_fn = __get_fn();
-                        __DISPATCHED_FN.store(__current_fn as *mut (), Ordering::Relaxed);
+                        let __current_fn: #fn_ty = match #dispatched_index() {
+                            #(#arms)*
+                            _ => #default_fn,
+                        };
+                        __DISPATCHED_FN.store(__current_fn as *mut ());
                         __current_fn(#(#argument_names),*)
                     }
-                    static __DISPATCHED_FN: AtomicPtr<()> = AtomicPtr::new(__resolver_fn as *mut ());
-                    let __current_ptr = __DISPATCHED_FN.load(Ordering::Relaxed);
+                    static __DISPATCHED_FN: #crate_path::__private::Dispatched =
+                        #crate_path::__private::Dispatched::new(__resolver_fn as *mut ());
+                    let __current_ptr = __DISPATCHED_FN.load();
                     unsafe {
                         let __current_fn = core::mem::transmute::<*mut (), #fn_ty>(__current_ptr);
                         __current_fn(#(#argument_names),*)
                     }
                 }
-            }
-        } else {
-            // Dispatch the function via branching if runtime-dispatching is disabled, or it is
-            // generic/async/impl Trait
-            let maybe_await = self.sig.asyncness.map(|_| util::await_tokens());
-            let maybe_self = if self.associated {
-                quote! { Self:: }
-            } else {
-                Default::default()
+                }
             };
-            let return_if_detected =
-                self.specializations
-                    .iter()
-                    .filter_map(|Specialization { target, .. }| {
-                        if target.has_features_specified() {
-                            let target_arch = target.target_arch();
-                            let features_detected = target.features_detected(&self.crate_path);
-                            let function = feature_fn_name(&self.sig.ident, Some(&target)).1;
-                            Some(quote! {
-                                #target_arch
-                                {
-                                    if #features_detected {
-                                        return #maybe_self#function::<#(#fn_params),*>(#(#argument_names),*)#maybe_await
-                                    }
-                                }
-                            })
-                        } else {
-                            None
+            if let Some(ifunc_cfg) = self.ifunc_cfg() {
+                // The dynamic loader calls the resolver once, when the ifunc symbol is bound.
+                // Features are detected with `cpuid`, since the standard library's detection isn't
+                // safe before the program starts.
+                // The selected index is stored for `{ident}_selected_version`.
+                let symbol = ifunc_symbol(&self.sig.ident);
+                let abi = self
+                    .sig
+                    .abi
+                    .clone()
+                    .unwrap_or_else(|| parse_quote! { extern "Rust" });
+                let ifunc_signature = Signature {
+                    constness: None,
+                    asyncness: None,
+                    unsafety: None,
+                    abi: None,
+                    ident: Ident::new("__multiversion_ifunc", Span::call_site()),
+                    ..normalized_signature.clone()
+                };
+                // The testing module can't be used before the program starts
+                let select_index = self.select_index(false, |target| {
+                    let features = target.features();
+                    quote! { #crate_path::__private::cpuid_features_detected(&[#(#features),*]) }
+                });
+                let resolver = Ident::new(
+                    &format!("__{}_ifunc_resolver", self.sig.ident),
+                    self.sig.ident.span(),
+                );
+                let ifunc_index = ifunc_index_static_name(&self.sig.ident);
+                let extern_unsafety = if cfg!(unsafe_extern_blocks) {
+                    quote! { unsafe }
+                } else {
+                    quote! {}
+                };
+                ifunc_items = quote! {
+                    #[cfg(#ifunc_cfg)]
+                    #[doc(hidden)]
+                    static #ifunc_index: core::sync::atomic::AtomicUsize =
+                        core::sync::atomic::AtomicUsize::new(0);
+                    #[cfg(#ifunc_cfg)]
+                    #[doc(hidden)]
+                    extern "C" fn #resolver() -> *mut () {
+                        #[allow(unreachable_code)]
+                        fn __multiversion_select() -> usize {
+                            #select_index
                         }
-                    });
+                        let __index = __multiversion_select();
+                        #ifunc_index.store(__index, core::sync::atomic::Ordering::Relaxed);
+                        let __current_fn: #fn_ty = match __index {
+                            #(#arms)*
+                            _ => #default_fn,
+                        };
+                        __current_fn as *mut ()
+                    }
+                    #[cfg(#ifunc_cfg)]
+                    ::core::arch::global_asm!(
+                        concat!(".globl \"", #symbol, "\""),
+                        concat!(".hidden \"", #symbol, "\""),
+                        concat!(".type \"", #symbol, "\", @gnu_indirect_function"),
+                        concat!(".set \"", #symbol, "\", {resolver}"),
+                        resolver = sym #resolver,
+                    );
+                };
+                parse_quote! {
+                    {
+                        #[cfg(#ifunc_cfg)]
+                        return {
+                            #extern_unsafety #abi {
+                                #[link_name = concat!(#symbol)]
+                                #ifunc_signature;
+                            }
+                            unsafe { __multiversion_ifunc(#(#argument_names),*) }
+                        };
+                        #[cfg(not(#ifunc_cfg))]
+                        return #atomic_block;
+                    }
+                }
+            } else {
+                atomic_block
+            }
+        } else if cfg!(feature = "std") {
+            // Generic, async, and impl Trait functions, and functions associated with generic
+            // implementations, can't be stored in a static function pointer, since statics can't
+            // use generic parameters.  Instead, the index of the selected version is cached, which
+            // is shared by every instantiation of the function.
+            self.index_dispatch_block()
+        } else {
+            // Dispatch the function via branching if runtime-dispatching is disabled
             let default_fn = feature_fn_name(&self.sig.ident, None).1;
+            let select_version = self.select_version(
+                true,
+                |_, target| target.features_detected(crate_path),
+                |_, target| {
+                    let function = feature_fn_name(&self.sig.ident, Some(target)).1;
+                    quote! { #maybe_self#function::<#(#fn_params),*>(#(#argument_names),*)#maybe_await }
+                },
+                quote! {
+                    return #maybe_self#default_fn::<#(#fn_params),*>(#(#argument_names),*)#maybe_await;
+                },
+            );
             parse_quote! {
                 {
-                    #(#return_if_detected)*
-                    #maybe_self#default_fn::<#(#fn_params),*>(#(#argument_names),*)#maybe_await
+                    #select_version
                 }
             }
         };
-        Ok(ItemFn {
+        let block = if return_direct.is_empty() {
+            block
+        } else {
+            parse_quote! {
+                {
+                    #(#return_direct)*
+                    #[cfg(not(#direct))]
+                    #block
+                }
+            }
+        };
+        let dispatcher = ItemFn {
             attrs: Vec::new(),
             vis: self.vis.clone(),
             sig: normalized_signature,
             block: Box::new(block),
+        };
+        Ok(quote! {
+            #ifunc_items
+            #dispatcher
         })
     }
-}
 
-impl ToTokens for Dispatcher {
-    fn to_tokens(&self, tokens: &mut TokenStream) {
-        tokens.extend(match self.feature_fns() {
+    // Create the versions of the function and the static dispatcher, which are emitted separately
+    // from the dispatcher in trait implementations
+    pub(crate) fn version_tokens(&self) -> TokenStream {
+        let mut tokens = match self.feature_fns() {
             Ok(val) => quote! { #(#val)* },
             Err(err) => err.to_compile_error(),
-        });
-        tokens.extend(match self.dispatcher_fn() {
-            Ok(val) => val.into_token_stream(),
+        };
+        tokens.extend(self.static_dispatch_fn().into_token_stream());
+        tokens.extend(self.versions_fn().into_token_stream());
+        tokens.extend(self.dispatched_index_fn().into_token_stream());
+        tokens.extend(self.selected_version_fn().into_token_stream());
+        tokens.extend(self.version_fns_const());
+        tokens
+    }
+
+    pub(crate) fn dispatcher_tokens(&self) -> TokenStream {
+        match self.dispatcher_fn() {
+            Ok(val) => val,
             Err(err) => err.to_compile_error(),
-        });
+        }
+    }
+}
+
+impl ToTokens for Dispatcher {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        tokens.extend(self.version_tokens());
+        tokens.extend(self.dispatcher_tokens());
     }
 }
@@ -0,0 +1,1018 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::collections::BTreeSet;
+
+/// A named microarchitecture level, which is shorthand for a fixed set of features.
+#[derive(Debug)]
+pub(crate) struct Level {
+    pub arch: &'static str,
+    pub names: &'static [&'static str],
+    pub mangled: &'static str,
+    pub features: &'static [&'static str],
+}
+
+// The x86-64 microarchitecture levels, as defined by the x86-64 psABI.  Each level includes the
+// features of the levels below it.  LAHF/SAHF is part of x86-64-v2, but is not a stable target
+// feature in Rust and is omitted.
+const X86_64_LEVELS: &[Level] = &[
+    Level {
+        arch: "x86_64",
+        names: &["x86_64-v2", "x86-64-v2"],
+        mangled: "x86_64_v2",
+        features: &["cmpxchg16b", "popcnt", "sse3", "sse4.1", "sse4.2", "ssse3"],
+    },
+    Level {
+        arch: "x86_64",
+        names: &["x86_64-v3", "x86-64-v3"],
+        mangled: "x86_64_v3",
+        features: &[
+            "avx",
+            "avx2",
+            "bmi1",
+            "bmi2",
+            "cmpxchg16b",
+            "f16c",
+            "fma",
+            "lzcnt",
+            "movbe",
+            "popcnt",
+            "sse3",
+            "sse4.1",
+            "sse4.2",
+            "ssse3",
+            "xsave",
+        ],
+    },
+    Level {
+        arch: "x86_64",
+        names: &["x86_64-v4", "x86-64-v4"],
+        mangled: "x86_64_v4",
+        features: &[
+            "avx",
+            "avx2",
+            "avx512bw",
+            "avx512cd",
+            "avx512dq",
+            "avx512f",
+            "avx512vl",
+            "bmi1",
+            "bmi2",
+            "cmpxchg16b",
+            "f16c",
+            "fma",
+            "lzcnt",
+            "movbe",
+            "popcnt",
+            "sse3",
+            "sse4.1",
+            "sse4.2",
+            "ssse3",
+            "xsave",
+        ],
+    },
+];
+
+/// Returns the microarchitecture levels, from lowest to highest.
+pub(crate) fn levels() -> std::slice::Iter<'static, Level> {
+    X86_64_LEVELS.iter()
+}
+
+/// Looks up a microarchitecture level by name (e.g. `x86_64-v3`).
+pub(crate) fn level(name: &str) -> Option<&'static Level> {
+    X86_64_LEVELS
+        .iter()
+        .find(|level| level.names.contains(&name))
+}
+
+/// A CPU model, which is shorthand for the features supported by that CPU.
+#[derive(Debug)]
+pub(crate) struct Cpu {
+    pub arches: &'static [&'static str],
+    pub name: &'static str,
+    pub base: Option<&'static str>,
+    pub features: &'static [&'static str],
+}
+
+const X86: &[&str] = &["x86", "x86_64"];
+const AARCH64: &[&str] = &["aarch64"];
+
+// Each CPU includes the features of its base CPU.  The names match the CPU names accepted by
+// rustc's `-C target-cpu`.
+const CPUS: &[Cpu] = &[
+    Cpu {
+        arches: X86,
+        name: "nehalem",
+        base: None,
+        features: &[
+            "cmpxchg16b",
+            "fxsr",
+            "popcnt",
+            "sse",
+            "sse2",
+            "sse3",
+            "sse4.1",
+            "sse4.2",
+            "ssse3",
+        ],
+    },
+    Cpu {
+        arches: X86,
+        name: "westmere",
+        base: Some("nehalem"),
+        features: &["aes", "pclmulqdq"],
+    },
+    Cpu {
+        arches: X86,
+        name: "sandybridge",
+        base: Some("westmere"),
+        features: &["avx", "xsave", "xsaveopt"],
+    },
+    Cpu {
+        arches: X86,
+        name: "ivybridge",
+        base: Some("sandybridge"),
+        features: &["f16c", "rdrand"],
+    },
+    Cpu {
+        arches: X86,
+        name: "haswell",
+        base: Some("ivybridge"),
+        features: &["avx2", "bmi1", "bmi2", "fma", "lzcnt", "movbe"],
+    },
+    Cpu {
+        arches: X86,
+        name: "broadwell",
+        base: Some("haswell"),
+        features: &["adx", "rdseed"],
+    },
+    Cpu {
+        arches: X86,
+        name: "skylake",
+        base: Some("broadwell"),
+        features: &["xsavec", "xsaves"],
+    },
+    Cpu {
+        arches: X86,
+        name: "skylake-avx512",
+        base: Some("skylake"),
+        features: &["avx512bw", "avx512cd", "avx512dq", "avx512f", "avx512vl"],
+    },
+    Cpu {
+        arches: X86,
+        name: "cascadelake",
+        base: Some("skylake-avx512"),
+        features: &["avx512vnni"],
+    },
+    Cpu {
+        arches: X86,
+        name: "icelake-client",
+        base: Some("cascadelake"),
+        features: &[
+            "avx512bitalg",
+            "avx512ifma",
+            "avx512vbmi",
+            "avx512vbmi2",
+            "avx512vpopcntdq",
+            "gfni",
+            "sha",
+            "vaes",
+            "vpclmulqdq",
+        ],
+    },
+    Cpu {
+        arches: X86,
+        name: "icelake-server",
+        base: Some("icelake-client"),
+        features: &[],
+    },
+    Cpu {
+        arches: X86,
+        name: "sapphirerapids",
+        base: Some("icelake-server"),
+        features: &["avx512bf16", "avx512fp16", "avxvnni"],
+    },
+    Cpu {
+        arches: X86,
+        name: "alderlake",
+        base: Some("skylake"),
+        features: &["avxvnni", "gfni", "sha", "vaes", "vpclmulqdq"],
+    },
+    Cpu {
+        arches: X86,
+        name: "znver1",
+        base: Some("broadwell"),
+        features: &["sha", "sse4a", "xsavec", "xsaves"],
+    },
+    Cpu {
+        arches: X86,
+        name: "znver2",
+        base: Some("znver1"),
+        features: &[],
+    },
+    Cpu {
+        arches: X86,
+        name: "znver3",
+        base: Some("znver2"),
+        features: &["vaes", "vpclmulqdq"],
+    },
+    Cpu {
+        arches: X86,
+        name: "znver4",
+        base: Some("znver3"),
+        features: &[
+            "avx512bf16",
+            "avx512bitalg",
+            "avx512bw",
+            "avx512cd",
+            "avx512dq",
+            "avx512f",
+            "avx512ifma",
+            "avx512vbmi",
+            "avx512vbmi2",
+            "avx512vl",
+            "avx512vnni",
+            "avx512vpopcntdq",
+            "gfni",
+        ],
+    },
+    Cpu {
+        arches: AARCH64,
+        name: "cortex-a72",
+        base: None,
+        features: &["aes", "crc", "neon", "sha2"],
+    },
+    Cpu {
+        arches: AARCH64,
+        name: "cortex-a76",
+        base: Some("cortex-a72"),
+        features: &["dotprod", "fp16", "lse", "rcpc", "rdm"],
+    },
+    Cpu {
+        arches: AARCH64,
+        name: "neoverse-n1",
+        base: Some("cortex-a76"),
+        features: &[],
+    },
+    Cpu {
+        arches: AARCH64,
+        name: "neoverse-v1",
+        base: Some("neoverse-n1"),
+        features: &["bf16", "i8mm", "rcpc2", "sve"],
+    },
+    Cpu {
+        arches: AARCH64,
+        name: "neoverse-n2",
+        base: Some("neoverse-n1"),
+        features: &["bf16", "i8mm", "rcpc2", "sve", "sve2"],
+    },
+    Cpu {
+        arches: AARCH64,
+        name: "neoverse-v2",
+        base: Some("neoverse-n2"),
+        features: &[],
+    },
+];
+
+/// Looks up a CPU model by name (e.g. `skylake`).
+pub(crate) fn cpu(name: &str) -> Option<&'static Cpu> {
+    CPUS.iter().find(|cpu| cpu.name == name)
+}
+
+impl Cpu {
+    /// The features supported by this CPU, including the features of its base CPU.
+    pub fn all_features(&self) -> Vec<&'static str> {
+        let mut features = self
+            .base
+            .and_then(cpu)
+            .map_or_else(Vec::new, Cpu::all_features);
+        features.extend(self.features);
+        features.sort_unstable();
+        features.dedup();
+        features
+    }
+
+    pub fn mangled(&self) -> String {
+        self.name.replace('-', "_")
+    }
+}
+
+// Features implied by other features, mirroring the implications used by rustc.  Only the
+// direct implications are listed.
+const X86_IMPLIED: &[(&str, &[&str])] = &[
+    ("aes", &["sse2"]),
+    ("avx", &["sse4.2"]),
+    ("avx2", &["avx"]),
+    ("avx512bf16", &["avx512bw"]),
+    ("avx512bitalg", &["avx512bw"]),
+    ("avx512bw", &["avx512f"]),
+    ("avx512cd", &["avx512f"]),
+    ("avx512dq", &["avx512f"]),
+    ("avx512f", &["avx2", "f16c", "fma"]),
+    ("avx512fp16", &["avx512bw"]),
+    ("avx512ifma", &["avx512f"]),
+    ("avx512vbmi", &["avx512bw"]),
+    ("avx512vbmi2", &["avx512bw"]),
+    ("avx512vl", &["avx512f"]),
+    ("avx512vnni", &["avx512f"]),
+    ("avx512vp2intersect", &["avx512f"]),
+    ("avx512vpopcntdq", &["avx512f"]),
+    ("avxifma", &["avx2"]),
+    ("avxneconvert", &["avx2"]),
+    ("avxvnni", &["avx2"]),
+    ("avxvnniint16", &["avx2"]),
+    ("avxvnniint8", &["avx2"]),
+    ("f16c", &["avx"]),
+    ("fma", &["avx"]),
+    ("gfni", &["sse2"]),
+    ("kl", &["sse2"]),
+    ("pclmulqdq", &["sse2"]),
+    ("sha", &["sse2"]),
+    ("sha512", &["avx2"]),
+    ("sm3", &["avx"]),
+    ("sm4", &["avx2"]),
+    ("sse2", &["sse"]),
+    ("sse3", &["sse2"]),
+    ("sse4.1", &["ssse3"]),
+    ("sse4.2", &["sse4.1"]),
+    ("sse4a", &["sse3"]),
+    ("ssse3", &["sse3"]),
+    ("vaes", &["aes", "avx2"]),
+    ("vpclmulqdq", &["avx", "pclmulqdq"]),
+    ("widekl", &["kl"]),
+    ("xsavec", &["xsave"]),
+    ("xsaveopt", &["xsave"]),
+    ("xsaves", &["xsave"]),
+];
+
+const AARCH64_IMPLIED: &[(&str, &[&str])] = &[
+    ("aes", &["neon"]),
+    ("bf16", &["neon"]),
+    ("dotprod", &["neon"]),
+    ("dpb2", &["dpb"]),
+    ("f32mm", &["sve"]),
+    ("f64mm", &["sve"]),
+    ("fcma", &["neon"]),
+    ("fhm", &["fp16"]),
+    ("fp16", &["neon"]),
+    ("i8mm", &["neon"]),
+    ("jsconv", &["neon"]),
+    ("rcpc2", &["rcpc"]),
+    ("rdm", &["neon"]),
+    ("sha2", &["neon"]),
+    ("sha3", &["sha2"]),
+    ("sm4", &["neon"]),
+    ("sve", &["neon"]),
+    ("sve2", &["sve"]),
+    ("sve2-aes", &["aes", "sve2"]),
+    ("sve2-bitperm", &["sve2"]),
+    ("sve2-sha3", &["sha3", "sve2"]),
+    ("sve2-sm4", &["sm4", "sve2"]),
+];
+
+const POWERPC_IMPLIED: &[(&str, &[&str])] = &[
+    ("power8-altivec", &["altivec"]),
+    ("power8-vector", &["power8-altivec", "vsx"]),
+    ("power9-altivec", &["power8-altivec"]),
+    ("power9-vector", &["power8-vector", "power9-altivec"]),
+    ("vsx", &["altivec"]),
+];
+
+const RISCV_IMPLIED: &[(&str, &[&str])] = &[
+    ("d", &["f"]),
+    ("v", &["d", "zve64d"]),
+    ("zfh", &["zfhmin"]),
+    ("zfhmin", &["f"]),
+    ("zk", &["zkn", "zkr", "zkt"]),
+    ("zkn", &["zbkb", "zbkc", "zbkx", "zknd", "zkne", "zknh"]),
+    ("zks", &["zbkb", "zbkc", "zbkx", "zksed", "zksh"]),
+    ("zve32f", &["f", "zve32x"]),
+    ("zve64d", &["d", "zve64f"]),
+    ("zve64f", &["zve32f", "zve64x"]),
+    ("zve64x", &["zve32x"]),
+    ("zvfh", &["zfhmin", "zvfhmin"]),
+    ("zvfhmin", &["zve32f"]),
+];
+
+const LOONGARCH_IMPLIED: &[(&str, &[&str])] = &[("d", &["f"]), ("lasx", &["lsx"]), ("lsx", &["d"])];
+
+const S390X_IMPLIED: &[(&str, &[&str])] = &[
+    ("nnp-assist", &["vector"]),
+    ("vector-enhancements-1", &["vector"]),
+    ("vector-enhancements-2", &["vector-enhancements-1"]),
+    ("vector-packed-decimal", &["vector"]),
+    (
+        "vector-packed-decimal-enhancement",
+        &["vector-packed-decimal"],
+    ),
+    (
+        "vector-packed-decimal-enhancement-2",
+        &["vector-packed-decimal-enhancement"],
+    ),
+];
+
+type Implications = &'static [(&'static str, &'static [&'static str])];
+
+const RISCV: &[&str] = &["riscv32", "riscv64"];
+const LOONGARCH: &[&str] = &["loongarch64"];
+const S390X: &[&str] = &["s390x"];
+
+const IMPLICATIONS: &[(&[&str], Implications)] = &[
+    (X86, X86_IMPLIED),
+    (AARCH64, AARCH64_IMPLIED),
+    (&["powerpc", "powerpc64"], POWERPC_IMPLIED),
+    (RISCV, RISCV_IMPLIED),
+    (LOONGARCH, LOONGARCH_IMPLIED),
+    (S390X, S390X_IMPLIED),
+];
+
+fn implications(arch: &str) -> Implications {
+    IMPLICATIONS
+        .iter()
+        .find(|(arches, _)| arches.contains(&arch))
+        .map_or(&[], |(_, implications)| implications)
+}
+
+/// Returns the features, along with every feature they imply on the architecture.
+pub(crate) fn implied_features<'a>(arch: &str, features: &[&'a str]) -> BTreeSet<&'a str> {
+    let implications = implications(arch);
+    let mut implied = BTreeSet::new();
+    let mut stack = features.to_vec();
+    while let Some(feature) = stack.pop() {
+        if implied.insert(feature) {
+            if let Some((_, features)) = implications.iter().find(|(f, _)| *f == feature) {
+                stack.extend(features.iter().copied());
+            }
+        }
+    }
+    implied
+}
+
+// The stable target features known for each architecture.
+const X86_FEATURES: &[&str] = &[
+    "adx",
+    "aes",
+    "avx",
+    "avx2",
+    "avx512bf16",
+    "avx512bitalg",
+    "avx512bw",
+    "avx512cd",
+    "avx512dq",
+    "avx512f",
+    "avx512fp16",
+    "avx512ifma",
+    "avx512vbmi",
+    "avx512vbmi2",
+    "avx512vl",
+    "avx512vnni",
+    "avx512vp2intersect",
+    "avx512vpopcntdq",
+    "avxifma",
+    "avxneconvert",
+    "avxvnni",
+    "avxvnniint16",
+    "avxvnniint8",
+    "bmi1",
+    "bmi2",
+    "cmpxchg16b",
+    "f16c",
+    "fma",
+    "fxsr",
+    "gfni",
+    "kl",
+    "lzcnt",
+    "movbe",
+    "pclmulqdq",
+    "popcnt",
+    "rdrand",
+    "rdseed",
+    "sha",
+    "sha512",
+    "sm3",
+    "sm4",
+    "sse",
+    "sse2",
+    "sse3",
+    "sse4.1",
+    "sse4.2",
+    "sse4a",
+    "ssse3",
+    "tbm",
+    "vaes",
+    "vpclmulqdq",
+    "widekl",
+    "xsave",
+    "xsavec",
+    "xsaveopt",
+    "xsaves",
+];
+
+const AARCH64_FEATURES: &[&str] = &[
+    "aes",
+    "bf16",
+    "bti",
+    "crc",
+    "dit",
+    "dotprod",
+    "dpb",
+    "dpb2",
+    "f32mm",
+    "f64mm",
+    "fcma",
+    "fhm",
+    "flagm",
+    "fp16",
+    "frintts",
+    "i8mm",
+    "jsconv",
+    "lor",
+    "lse",
+    "mte",
+    "neon",
+    "paca",
+    "pacg",
+    "pan",
+    "pmuv3",
+    "rand",
+    "ras",
+    "rcpc",
+    "rcpc2",
+    "rdm",
+    "sb",
+    "sha2",
+    "sha3",
+    "sm4",
+    "spe",
+    "ssbs",
+    "sve",
+    "sve2",
+    "sve2-aes",
+    "sve2-bitperm",
+    "sve2-sha3",
+    "sve2-sm4",
+    "tme",
+    "vh",
+];
+
+const ARM_FEATURES: &[&str] = &[
+    "aclass",
+    "aes",
+    "crc",
+    "d32",
+    "dotprod",
+    "dsp",
+    "fp-armv8",
+    "i8mm",
+    "mclass",
+    "neon",
+    "rclass",
+    "sha2",
+    "thumb-mode",
+    "thumb2",
+    "trustzone",
+    "v5te",
+    "v6",
+    "v6k",
+    "v6t2",
+    "v7",
+    "v8",
+    "vfp2",
+    "vfp3",
+    "vfp4",
+    "virtualization",
+];
+
+const POWERPC_FEATURES: &[&str] = &[
+    "altivec",
+    "partword-atomics",
+    "power10-vector",
+    "power8-altivec",
+    "power8-crypto",
+    "power8-vector",
+    "power9-altivec",
+    "power9-vector",
+    "quadword-atomics",
+    "vsx",
+];
+
+const MIPS_FEATURES: &[&str] = &["fp64", "msa", "virt"];
+
+const RISCV_FEATURES: &[&str] = &[
+    "a",
+    "c",
+    "d",
+    "f",
+    "m",
+    "unaligned-scalar-mem",
+    "unaligned-vector-mem",
+    "v",
+    "zaamo",
+    "zabha",
+    "zacas",
+    "zalrsc",
+    "zawrs",
+    "zba",
+    "zbb",
+    "zbc",
+    "zbkb",
+    "zbkc",
+    "zbkx",
+    "zbs",
+    "zca",
+    "zcb",
+    "zcd",
+    "zcf",
+    "zcmop",
+    "zdinx",
+    "zfa",
+    "zfbfmin",
+    "zfh",
+    "zfhmin",
+    "zfinx",
+    "zhinx",
+    "zhinxmin",
+    "zicbom",
+    "zicboz",
+    "zicntr",
+    "zicond",
+    "zicsr",
+    "zifencei",
+    "zihintntl",
+    "zihintpause",
+    "zihpm",
+    "zimop",
+    "zk",
+    "zkn",
+    "zknd",
+    "zkne",
+    "zknh",
+    "zkr",
+    "zks",
+    "zksed",
+    "zksh",
+    "zkt",
+    "ztso",
+    "zvbb",
+    "zvbc",
+    "zve32f",
+    "zve32x",
+    "zve64d",
+    "zve64f",
+    "zve64x",
+    "zvfbfmin",
+    "zvfbfwma",
+    "zvfh",
+    "zvfhmin",
+    "zvkb",
+    "zvkg",
+    "zvkn",
+    "zvknc",
+    "zvkned",
+    "zvkng",
+    "zvknha",
+    "zvknhb",
+    "zvks",
+    "zvksc",
+    "zvksed",
+    "zvksg",
+    "zvksh",
+    "zvkt",
+];
+
+const LOONGARCH_FEATURES: &[&str] = &[
+    "d",
+    "div32",
+    "f",
+    "frecipe",
+    "lam-bh",
+    "lamcas",
+    "lasx",
+    "lbt",
+    "ld-seq-sa",
+    "lsx",
+    "lvz",
+    "scq",
+];
+
+const S390X_FEATURES: &[&str] = &[
+    "concurrent-functions",
+    "deflate-conversion",
+    "enhanced-sort",
+    "guarded-storage",
+    "high-word",
+    "message-security-assist-extension12",
+    "message-security-assist-extension3",
+    "message-security-assist-extension4",
+    "message-security-assist-extension5",
+    "message-security-assist-extension8",
+    "message-security-assist-extension9",
+    "miscellaneous-extensions-2",
+    "miscellaneous-extensions-3",
+    "miscellaneous-extensions-4",
+    "nnp-assist",
+    "transactional-execution",
+    "vector",
+    "vector-enhancements-1",
+    "vector-enhancements-2",
+    "vector-enhancements-3",
+    "vector-packed-decimal",
+    "vector-packed-decimal-enhancement",
+    "vector-packed-decimal-enhancement-2",
+    "vector-packed-decimal-enhancement-3",
+];
+
+const WASM_FEATURES: &[&str] = &[
+    "atomics",
+    "bulk-memory",
+    "exception-handling",
+    "extended-const",
+    "multivalue",
+    "mutable-globals",
+    "nontrapping-fptoint",
+    "reference-types",
+    "relaxed-simd",
+    "sign-ext",
+    "simd128",
+    "tail-call",
+];
+
+// The features that can be detected at runtime, which are cached in a bitmask.  The position of
+// each feature is its bit, and bit 63 is reserved, so each architecture has at most 63 features.
+const DETECTABLE: &[(&[&str], &[&str])] = &[
+    // Features that the minimum supported Rust version can't detect aren't cached, and are
+    // detected when needed instead
+    (
+        X86,
+        &[
+            "adx",
+            "aes",
+            "avx",
+            "avx2",
+            "avx512bf16",
+            "avx512bitalg",
+            "avx512bw",
+            "avx512cd",
+            "avx512dq",
+            "avx512f",
+            "avx512ifma",
+            "avx512vbmi",
+            "avx512vbmi2",
+            "avx512vl",
+            "avx512vnni",
+            "avx512vp2intersect",
+            "avx512vpopcntdq",
+            "bmi1",
+            "bmi2",
+            "cmpxchg16b",
+            "f16c",
+            "fma",
+            "fxsr",
+            "lzcnt",
+            "pclmulqdq",
+            "popcnt",
+            "rdrand",
+            "rdseed",
+            "sha",
+            "sse",
+            "sse2",
+            "sse3",
+            "sse4.1",
+            "sse4.2",
+            "sse4a",
+            "ssse3",
+            "tbm",
+            "xsave",
+            "xsavec",
+            "xsaveopt",
+            "xsaves",
+        ],
+    ),
+    (
+        AARCH64,
+        &[
+            "aes",
+            "bf16",
+            "bti",
+            "crc",
+            "dit",
+            "dotprod",
+            "dpb",
+            "dpb2",
+            "f32mm",
+            "f64mm",
+            "fcma",
+            "fhm",
+            "flagm",
+            "fp16",
+            "frintts",
+            "i8mm",
+            "jsconv",
+            "lse",
+            "mte",
+            "neon",
+            "paca",
+            "pacg",
+            "rand",
+            "rcpc",
+            "rcpc2",
+            "rdm",
+            "sb",
+            "sha2",
+            "sha3",
+            "sm4",
+            "ssbs",
+            "sve",
+            "sve2",
+            "sve2-aes",
+            "sve2-bitperm",
+            "sve2-sha3",
+            "sve2-sm4",
+            "tme",
+        ],
+    ),
+    // Only features with stable runtime detection are cached, and the rest are detected when needed
+    (RISCV, &["v", "zba", "zbb"]),
+    (LOONGARCH, &["lasx", "lsx"]),
+    (S390X, &["vector"]),
+];
+
+fn detectable(arch: &str) -> Option<&'static [&'static str]> {
+    DETECTABLE
+        .iter()
+        .find(|(arches, _)| arches.contains(&arch))
+        .map(|(_, features)| *features)
+}
+
+// Known features that the standard library can't detect at runtime, which can be enabled with
+// `#[target]` but can't be selected by a dispatcher
+const UNDETECTABLE: &[(&[&str], &[&str])] = &[
+    (AARCH64, &["lor", "pan", "pmuv3", "ras", "spe", "vh"]),
+    (
+        &["arm"],
+        &[
+            "aclass",
+            "d32",
+            "dsp",
+            "fp-armv8",
+            "mclass",
+            "rclass",
+            "thumb-mode",
+            "thumb2",
+            "trustzone",
+            "v5te",
+            "v6",
+            "v6k",
+            "v6t2",
+            "v7",
+            "v8",
+            "vfp2",
+            "vfp3",
+            "vfp4",
+            "virtualization",
+        ],
+    ),
+];
+
+/// Returns true unless the feature is known to be undetectable at runtime on the architecture.
+pub(crate) fn is_detectable(arch: &str, feature: &str) -> bool {
+    !UNDETECTABLE
+        .iter()
+        .any(|(arches, features)| arches.contains(&arch) && features.contains(&feature))
+}
+
+/// Returns the bitmask of detected features required by the features, or `None` if any feature
+/// isn't in the bitmask or implied by a feature in the bitmask.
+pub(crate) fn feature_mask(arch: &str, features: &[&str]) -> Option<u64> {
+    let detectable = detectable(arch)?;
+    let cached = features
+        .iter()
+        .copied()
+        .filter(|feature| detectable.contains(feature))
+        .collect::<Vec<_>>();
+    let implied = implied_features(arch, &cached);
+    features.iter().try_fold(0, |mask, feature| {
+        match detectable.iter().position(|known| known == feature) {
+            Some(bit) => Some(mask | 1 << bit),
+            None if implied.contains(feature) => Some(mask),
+            None => None,
+        }
+    })
+}
+
+/// Returns the known features for an architecture, or `None` if the architecture is unknown.
+pub(crate) fn known_features(arch: &str) -> Option<&'static [&'static str]> {
+    match arch {
+        "x86" | "x86_64" => Some(X86_FEATURES),
+        "aarch64" => Some(AARCH64_FEATURES),
+        "arm" => Some(ARM_FEATURES),
+        "powerpc" | "powerpc64" => Some(POWERPC_FEATURES),
+        "mips" | "mips64" => Some(MIPS_FEATURES),
+        "riscv32" | "riscv64" => Some(RISCV_FEATURES),
+        "loongarch64" => Some(LOONGARCH_FEATURES),
+        "s390x" => Some(S390X_FEATURES),
+        "wasm32" | "wasm64" => Some(WASM_FEATURES),
+        _ => None,
+    }
+}
+
+/// Returns the known feature most similar to the misspelled feature, if any are similar enough.
+pub(crate) fn suggest_feature(arch: &str, feature: &str) -> Option<&'static str> {
+    known_features(arch)?
+        .iter()
+        .map(|known| (edit_distance(known, feature), *known))
+        .filter(|(distance, _)| *distance <= 2.max(feature.len() / 3))
+        .min_by_key(|(distance, known)| (*distance, known.len().abs_diff(feature.len())))
+        .map(|(_, known)| known)
+}
+
+// The Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + if ca == *cb { 0 } else { 1 };
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+// Creates the tables of levels and implied features used by the runtime crate, so the feature
+// database is only maintained here
+pub(crate) fn runtime_tables() -> TokenStream {
+    let levels = levels().flat_map(|level| {
+        let arch = level.arch;
+        let features = level.features;
+        level
+            .names
+            .iter()
+            .map(move |name| quote! { (#arch, #name, &[#(#features),*]) })
+    });
+    let implied = IMPLICATIONS.iter().map(|(arches, implications)| {
+        let implications = implications
+            .iter()
+            .map(|(feature, implied)| quote! { (#feature, &[#(#implied),*]) });
+        quote! { (&[#(#arches),*], &[#(#implications),*]) }
+    });
+    quote! {
+        pub(crate) const LEVELS: &[(&str, &str, &[&str])] = &[#(#levels),*];
+        pub(crate) const IMPLIED: &[(&[&str], &[(&str, &[&str])])] = &[#(#implied),*];
+    }
+}
+
+// Creates the detectable features and a function detecting them, used by the runtime crate
+pub(crate) fn cpu_features() -> TokenStream {
+    let arches = DETECTABLE
+        .iter()
+        .flat_map(|(arches, _)| arches.iter())
+        .collect::<Vec<_>>();
+    let tables = DETECTABLE.iter().map(|(arches, features)| {
+        let detected = features.iter().enumerate().map(|(bit, feature)| {
+            quote! {
+                if crate::are_cpu_features_detected!(#feature) {
+                    bits |= 1 << #bit;
+                }
+            }
+        });
+        quote! {
+            #[cfg(any(#(target_arch = #arches),*))]
+            pub(crate) const NAMES: &[&str] = &[#(#features),*];
+
+            #[cfg(any(#(target_arch = #arches),*))]
+            pub(crate) fn detect() -> u64 {
+                let mut bits = 0;
+                #(#detected)*
+                bits
+            }
+        }
+    });
+    quote! {
+        #(#tables)*
+
+        #[cfg(not(any(#(target_arch = #arches),*)))]
+        pub(crate) const NAMES: &[&str] = &[];
+
+        #[cfg(not(any(#(target_arch = #arches),*)))]
+        pub(crate) fn detect() -> u64 {
+            0
+        }
+    }
+}
+
+// Creates the CPU models, with every feature they support, used by the testing module
+pub(crate) fn cpu_models() -> TokenStream {
+    let cpus = CPUS.iter().map(|cpu| {
+        let arches = cpu.arches;
+        let name = cpu.name;
+        let features = implied_features(arches[0], &cpu.all_features());
+        let features = features.iter();
+        quote! { (&[#(#arches),*], #name, &[#(#features),*]) }
+    });
+    quote! {
+        pub(crate) const CPUS: &[(&[&str], &str, &[&str])] = &[#(#cpus),*];
+    }
+}
@@ -4,25 +4,28 @@ extern crate proc_macro;
 #[macro_use]
 mod meta;
 
+mod dispatch_region;
 mod dispatcher;
+mod features;
 mod multiversion;
 mod safe_inner;
 mod static_dispatch;
 mod target;
 mod target_cfg;
+mod target_set;
 mod util;
 
 use quote::ToTokens;
-use syn::{parse::Nothing, parse_macro_input, ItemFn};
+use syn::{parse_macro_input, Item, ItemFn};
 
 #[proc_macro_attribute]
 pub fn multiversion(
     attr: proc_macro::TokenStream,
     input: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    parse_macro_input!(attr as Nothing);
-    let func = parse_macro_input!(input as ItemFn);
-    match multiversion::make_multiversioned_fn(func) {
+    let args = parse_macro_input!(attr as multiversion::Args);
+    let item = parse_macro_input!(input as Item);
+    match multiversion::make_multiversioned_item(args, item) {
         Ok(tokens) => tokens.into_token_stream(),
         Err(err) => err.to_compile_error(),
     }
@@ -42,3 +45,44 @@ pub fn target(
     }
     .into()
 }
+
+#[proc_macro]
+pub fn target_set(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let set = parse_macro_input!(input as target_set::TargetSet);
+    match target_set::make_target_set(set) {
+        Ok(tokens) => tokens,
+        Err(err) => err.to_compile_error(),
+    }
+    .into()
+}
+
+#[proc_macro]
+pub fn dispatch_region(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let region = parse_macro_input!(input as dispatch_region::DispatchRegion);
+    match dispatch_region::make_dispatch_region(region) {
+        Ok(tokens) => tokens,
+        Err(err) => err.to_compile_error(),
+    }
+    .into()
+}
+
+#[doc(hidden)]
+#[proc_macro]
+pub fn __feature_tables(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    parse_macro_input!(input as syn::parse::Nothing);
+    features::runtime_tables().into()
+}
+
+#[doc(hidden)]
+#[proc_macro]
+pub fn __cpu_features(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    parse_macro_input!(input as syn::parse::Nothing);
+    features::cpu_features().into()
+}
+
+#[doc(hidden)]
+#[proc_macro]
+pub fn __cpu_models(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    parse_macro_input!(input as syn::parse::Nothing);
+    features::cpu_models().into()
+}
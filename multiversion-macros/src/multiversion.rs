@@ -1,11 +1,53 @@
 use crate::dispatcher::Dispatcher;
 use crate::meta::{parse_attributes, parse_crate_path};
-use crate::target::Target;
+use crate::target::{check_target_order, Target};
 use crate::util;
-use proc_macro2::TokenStream;
+use proc_macro2::{Span, TokenStream};
 use quote::{quote, ToTokens};
 use std::convert::{TryFrom, TryInto};
-use syn::{parse_quote, spanned::Spanned, Error, ItemFn, Lit, Meta, NestedMeta, Path};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_quote,
+    punctuated::Punctuated,
+    spanned::Spanned,
+    token::Comma,
+    Error, Ident, ImplItem, Item, ItemFn, ItemImpl, Lit, Meta, NestedMeta, Path, Token, Visibility,
+};
+
+pub(crate) struct Args {
+    targets: Option<Path>,
+    ifunc: Option<Ident>,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> Result<Self, Error> {
+        let mut targets = None;
+        let mut ifunc = None;
+        while !input.is_empty() {
+            let key = input.parse::<Ident>()?;
+            match key.to_string().as_str() {
+                "targets" => {
+                    if targets.is_some() {
+                        return Err(Error::new(key.span(), "key already provided"));
+                    }
+                    input.parse::<Token![=]>()?;
+                    targets = Some(input.parse()?);
+                }
+                "ifunc" => {
+                    if ifunc.is_some() {
+                        return Err(Error::new(key.span(), "key already provided"));
+                    }
+                    ifunc = Some(key);
+                }
+                _ => return Err(Error::new(key.span(), "unexpected key")),
+            }
+            if !input.is_empty() {
+                input.parse::<Comma>()?;
+            }
+        }
+        Ok(Self { targets, ifunc })
+    }
+}
 
 enum Specialization {
     Clone {
@@ -18,10 +60,19 @@ enum Specialization {
     },
 }
 
+impl Specialization {
+    fn target(&self) -> &Target {
+        match self {
+            Self::Clone { target } | Self::Override { target, .. } => target,
+        }
+    }
+}
+
 struct Function {
     specializations: Vec<Specialization>,
     func: ItemFn,
     associated: bool,
+    ifunc: bool,
     crate_path: Path,
 }
 
@@ -31,6 +82,7 @@ impl TryFrom<Function> for Dispatcher {
     fn try_from(item: Function) -> Result<Self, Self::Error> {
         let (_, args) = util::normalize_signature(&item.func.sig);
         let fn_params = util::fn_params(&item.func.sig);
+        check_target_order(&item.specializations, Specialization::target)?;
         Ok(Self {
             specializations: item
                 .specializations
@@ -73,11 +125,32 @@ impl TryFrom<Function> for Dispatcher {
             sig: item.func.sig,
             default: *item.func.block,
             associated: item.associated,
+            concrete_self: false,
+            versions_trait: None,
+            ifunc: item.ifunc,
             crate_path: item.crate_path,
         })
     }
 }
 
+pub(crate) fn parse_target(
+    nested: &Punctuated<NestedMeta, Comma>,
+    target: Option<&Lit>,
+    cpu: Option<&Lit>,
+) -> Result<Target, Error> {
+    let mut target: Target = target
+        .ok_or_else(|| Error::new(nested.span(), "expected key 'target'"))?
+        .try_into()?;
+    if let Some(cpu) = cpu {
+        match cpu {
+            Lit::Str(s) => target.set_cpu(s)?,
+            lit => return Err(Error::new(lit.span(), "expected literal string")),
+        }
+    }
+    target.check_detectable()?;
+    Ok(target)
+}
+
 impl TryFrom<ItemFn> for Function {
     type Error = Error;
 
@@ -86,6 +159,7 @@ impl TryFrom<ItemFn> for Function {
         let mut multiversioned = Function {
             specializations: Vec::new(),
             associated,
+            ifunc: false,
             crate_path: parse_quote!(multiversion),
             func: ItemFn {
                 attrs: Vec::new(),
@@ -103,12 +177,11 @@ impl TryFrom<ItemFn> for Function {
                     meta_parser! {
                         nested => [
                             "target" => target,
+                            "cpu" => cpu,
                         ]
                     }
                     multiversioned.specializations.push(Specialization::Clone {
-                        target: target
-                            .ok_or_else(|| Error::new(nested.span(), "expected key 'target'"))?
-                            .try_into()?,
+                        target: parse_target(nested, target, cpu)?,
                     });
                     true
                 }
@@ -116,6 +189,7 @@ impl TryFrom<ItemFn> for Function {
                     meta_parser! {
                         nested => [
                             "target" => target,
+                            "cpu" => cpu,
                             "fn" => func,
                             "unsafe" => is_unsafe,
                         ]
@@ -123,9 +197,7 @@ impl TryFrom<ItemFn> for Function {
                     multiversioned
                         .specializations
                         .push(Specialization::Override {
-                            target: target
-                                .ok_or_else(|| Error::new(nested.span(), "expected key 'target'"))?
-                                .try_into()?,
+                            target: parse_target(nested, target, cpu)?,
                             func: match func
                                 .ok_or_else(|| Error::new(nested.span(), "expected key 'fn'"))?
                             {
@@ -147,8 +219,217 @@ impl TryFrom<ItemFn> for Function {
     }
 }
 
-pub(crate) fn make_multiversioned_fn(func: ItemFn) -> Result<TokenStream, syn::Error> {
-    let function: Function = func.try_into()?;
+pub(crate) fn make_multiversioned_fn(func: ItemFn, ifunc: bool) -> Result<TokenStream, syn::Error> {
+    let mut function: Function = func.try_into()?;
+    function.ifunc = ifunc;
     let dispatcher: Dispatcher = function.try_into()?;
-    Ok(dispatcher.to_token_stream())
+
+    Ok(dispatcher.into_token_stream())
+}
+
+// Creates a private trait containing the versions of the methods of a trait implementation, since
+// the trait implementation can only contain members of the trait.  A separate trait, rather than
+// an inherent implementation, allows implementing foreign traits for foreign types, and methods
+// with the same name in different trait implementations.
+fn make_versions_trait(
+    item: &ItemImpl,
+    versions_trait: &Ident,
+    versions: Vec<TokenStream>,
+) -> Result<TokenStream, syn::Error> {
+    let versions: ItemImpl = syn::parse2(quote! { impl __MultiversionVersions { #(#versions)* } })?;
+    let mut declarations = Vec::new();
+    let mut items = Vec::new();
+    for mut version in versions.items {
+        match &mut version {
+            ImplItem::Method(method) => {
+                method.vis = Visibility::Inherited;
+                let cfgs = method.attrs.iter().filter(|attr| attr.path.is_ident("cfg"));
+                // Patterns aren't allowed in declarations
+                let sig = util::normalize_signature(&method.sig).0;
+                declarations.push(quote! { #(#cfgs)* #sig; });
+            }
+            ImplItem::Const(constant) => {
+                constant.vis = Visibility::Inherited;
+                let cfgs = constant
+                    .attrs
+                    .iter()
+                    .filter(|attr| attr.path.is_ident("cfg"));
+                let ident = &constant.ident;
+                let ty = &constant.ty;
+                declarations.push(quote! { #(#cfgs)* const #ident: #ty; });
+            }
+            version => return Err(Error::new(version.span(), "unexpected version item")),
+        }
+        items.push(version);
+    }
+
+    let cfgs = item.attrs.iter().filter(|attr| attr.path.is_ident("cfg"));
+    let cfgs = quote! { #(#cfgs)* };
+    let generics = &item.generics;
+    let (impl_generics, ty_generics, where_clause) = item.generics.split_for_impl();
+    let self_ty = &item.self_ty;
+    Ok(quote! {
+        #cfgs
+        #[doc(hidden)]
+        #[allow(dead_code, non_camel_case_types)]
+        trait #versions_trait #generics #where_clause {
+            #(#declarations)*
+        }
+
+        #cfgs
+        impl #impl_generics #versions_trait #ty_generics for #self_ty #where_clause {
+            #(#items)*
+        }
+    })
+}
+
+pub(crate) fn make_multiversioned_impl(mut item: ItemImpl) -> Result<TokenStream, syn::Error> {
+    // Separate the attributes that apply to every method in the block
+    let mut shared = Vec::new();
+    let mut retained = Vec::new();
+    for attr in item.attrs.drain(..) {
+        if attr.path.is_ident("clone") || attr.path.is_ident("crate_path") {
+            shared.push(attr);
+        } else if attr.path.is_ident("specialize") {
+            return Err(Error::new(
+                attr.span(),
+                "`specialize` must be applied to individual methods",
+            ));
+        } else {
+            retained.push(attr);
+        }
+    }
+    item.attrs = retained;
+
+    let associated_types = item
+        .items
+        .iter()
+        .filter_map(|impl_item| match impl_item {
+            ImplItem::Type(ty) if ty.generics.params.is_empty() => {
+                Some((ty.ident.clone(), ty.ty.clone()))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // The name of the trait containing the versions of a trait implementation's methods is derived
+    // from the trait and type, so it's unique to the implementation within the module
+    let versions_trait = item.trait_.as_ref().map(|(_, trait_path, _)| {
+        let self_ty = &item.self_ty;
+        let name = format!("{}_for_{}", quote!(#trait_path), quote!(#self_ty))
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        Ident::new(
+            &format!("__MultiversionVersions_{}", name),
+            Span::call_site(),
+        )
+    });
+    let versions_trait_path: Option<Path> = versions_trait.as_ref().map(|versions_trait| {
+        let (_, ty_generics, _) = item.generics.split_for_impl();
+        parse_quote! { #versions_trait #ty_generics }
+    });
+
+    let mut items = Vec::new();
+    let mut versions = Vec::new();
+    for impl_item in item.items.drain(..) {
+        if let ImplItem::Method(mut method) = impl_item {
+            // The block's attribute covers the method, so `#[multiversion]` may be repeated on it
+            method.attrs.retain(|attr| {
+                attr.path
+                    .segments
+                    .last()
+                    .map_or(true, |segment| segment.ident != "multiversion")
+            });
+            // The method's own targets are more specific, so they're selected first
+            let mut func = ItemFn {
+                attrs: method.attrs.iter().chain(&shared).cloned().collect(),
+                vis: method.vis.clone(),
+                sig: method.sig.clone(),
+                block: Box::new(method.block.clone()),
+            };
+            util::replace_associated_types(&mut func, &associated_types);
+            let mut function: Function = func.try_into()?;
+
+            // Methods without any targets are left unchanged
+            if function.specializations.is_empty() {
+                items.push(ImplItem::Method(method));
+                continue;
+            }
+
+            function.associated = true;
+            let mut dispatcher: Dispatcher = function.try_into()?;
+            // The versions of methods in generic implementations can't share a function pointer
+            dispatcher.concrete_self = item.generics.params.is_empty();
+            dispatcher.versions_trait = versions_trait_path.clone();
+            if item.trait_.is_some() {
+                versions.push(dispatcher.version_tokens());
+                items.push(ImplItem::Verbatim(dispatcher.dispatcher_tokens()));
+            } else {
+                items.push(ImplItem::Verbatim(dispatcher.to_token_stream()));
+            }
+        } else {
+            items.push(impl_item);
+        }
+    }
+    item.items = items;
+
+    let versions_trait = match versions_trait {
+        Some(versions_trait) if !versions.is_empty() => {
+            make_versions_trait(&item, &versions_trait, versions)?
+        }
+        _ => TokenStream::new(),
+    };
+    Ok(quote! {
+        #item
+        #versions_trait
+    })
+}
+
+fn expected_item(item: &Item) -> Error {
+    Error::new(item.span(), "expected a function or impl block")
+}
+
+// Target sets are macros that add their targets to the item and apply this attribute again
+fn apply_target_set(
+    targets: &Path,
+    ifunc: Option<&Ident>,
+    item: Item,
+) -> Result<TokenStream, Error> {
+    let attrs = match &item {
+        Item::Fn(func) => &func.attrs,
+        Item::Impl(item) => &item.attrs,
+        item => return Err(expected_item(item)),
+    };
+    let mut crate_path = parse_quote!(multiversion);
+    parse_attributes(attrs.iter().cloned(), |path, nested| {
+        if path == "crate_path" {
+            crate_path = parse_crate_path(nested)?;
+        }
+        Ok(false)
+    })?;
+    let args = ifunc.map(|ifunc| quote! { (#ifunc) });
+    Ok(quote! {
+        #targets! { #crate_path::multiversion#args, #item }
+    })
+}
+
+pub(crate) fn make_multiversioned_item(args: Args, item: Item) -> Result<TokenStream, Error> {
+    if let Some(targets) = args.targets {
+        return apply_target_set(&targets, args.ifunc.as_ref(), item);
+    }
+    match item {
+        Item::Fn(func) => make_multiversioned_fn(func, args.ifunc.is_some()),
+        Item::Impl(item) => {
+            if let Some(ifunc) = args.ifunc {
+                return Err(Error::new(
+                    ifunc.span(),
+                    "`ifunc` is not supported for associated functions",
+                ));
+            }
+            make_multiversioned_impl(item)
+        }
+        item => Err(expected_item(&item)),
+    }
 }
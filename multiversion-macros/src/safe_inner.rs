@@ -1,8 +1,12 @@
 use crate::util::is_associated_fn;
+use proc_macro2::TokenStream;
 use quote::quote;
 use syn::{parse_quote, spanned::Spanned, Error, Ident, ItemFn, Result, Signature, Visibility};
 
-pub fn process_safe_inner(mut item: ItemFn) -> Result<Vec<ItemFn>> {
+pub fn process_safe_inner(
+    mut item: ItemFn,
+    maybe_self: Option<&TokenStream>,
+) -> Result<Vec<ItemFn>> {
     let safe_inner_span = {
         if let Some((idx, attr)) = item
             .attrs
@@ -58,10 +62,10 @@ pub fn process_safe_inner(mut item: ItemFn) -> Result<Vec<ItemFn>> {
         // create unsafe function
         let (unsafe_sig, args) = crate::util::normalize_signature(&item.sig);
         let maybe_await = item.sig.asyncness.map(|_| crate::util::await_tokens());
-        let maybe_self = if associated {
-            quote! { Self:: }
-        } else {
-            Default::default()
+        let maybe_self = match maybe_self {
+            Some(maybe_self) => maybe_self.clone(),
+            None if associated => quote! { Self:: },
+            None => TokenStream::new(),
         };
         let safe_ident = &safe_fn.sig.ident;
         let fn_params = crate::util::fn_params(&unsafe_sig);
@@ -1,20 +1,23 @@
-use crate::dispatcher::feature_fn_name;
+use crate::dispatcher::{feature_fn_name, static_dispatch_fn_name};
 use crate::target::Target;
 use syn::{
+    parse_quote,
     spanned::Spanned,
     visit_mut::{self, VisitMut},
-    Error, Expr, ItemFn, Result,
+    Block, Error, Expr, Path, Result,
 };
 
 struct StaticDispatchVisitor<'a> {
     target: Option<&'a Target>,
+    crate_path: &'a Path,
     status: Result<()>,
 }
 
 impl<'a> StaticDispatchVisitor<'a> {
-    pub fn new(target: Option<&'a Target>) -> Self {
+    pub fn new(target: Option<&'a Target>, crate_path: &'a Path) -> Self {
         Self {
             target,
+            crate_path,
             status: Ok(()),
         }
     }
@@ -24,15 +27,34 @@ impl<'a> StaticDispatchVisitor<'a> {
     }
 }
 
-fn dispatch_impl(expr: &mut Expr, target: Option<&Target>) -> Result<()> {
+// Creates a value describing the features of the calling function, which the static dispatcher
+// uses to select the best version.
+fn caller_features(target: Option<&Target>, crate_path: &Path) -> Expr {
+    let features = target.into_iter().flat_map(Target::features);
+    parse_quote! {
+        {
+            struct __MultiversionCaller;
+            impl #crate_path::__private::TargetFeatures for __MultiversionCaller {
+                const FEATURES: &'static [&'static str] = &[#(#features),*];
+            }
+            __MultiversionCaller
+        }
+    }
+}
+
+fn dispatch_impl(expr: &mut Expr, target: Option<&Target>, crate_path: &Path) -> Result<()> {
     if let Expr::Macro(macro_expr) = expr {
         if let Some(path_ident) = macro_expr.mac.path.get_ident() {
             if path_ident.to_string().as_str() == "dispatch" {
                 let mut call = macro_expr.mac.parse_body::<Expr>()?;
-                let ident = match &mut call {
+                match &mut call {
+                    // Function calls select the best version supported by the caller
                     Expr::Call(ref mut call) => {
                         if let Expr::Path(ref mut function) = *call.func {
-                            Ok(&mut function.path.segments.last_mut().unwrap().ident)
+                            let ident = &mut function.path.segments.last_mut().unwrap().ident;
+                            *ident = static_dispatch_fn_name(ident);
+                            call.args.push(caller_features(target, crate_path));
+                            Ok(())
                         } else {
                             Err(Error::new(
                                 call.func.span(),
@@ -40,16 +62,22 @@ fn dispatch_impl(expr: &mut Expr, target: Option<&Target>) -> Result<()> {
                             ))
                         }
                     }
-                    Expr::MethodCall(call) => Ok(&mut call.method),
+                    Expr::MethodCall(call) => {
+                        call.method = static_dispatch_fn_name(&call.method);
+                        call.args.push(caller_features(target, crate_path));
+                        Ok(())
+                    }
+                    // Function paths require a version that exactly matches the caller
                     Expr::Path(ref mut path) => {
-                        Ok(&mut path.path.segments.last_mut().unwrap().ident)
+                        let ident = &mut path.path.segments.last_mut().unwrap().ident;
+                        *ident = feature_fn_name(ident, target).1;
+                        Ok(())
                     }
                     _ => Err(Error::new(
                         call.span(),
                         "expected a function or method call",
                     )),
                 }?;
-                *ident = feature_fn_name(ident, target).1;
                 *expr = call;
             }
         }
@@ -60,7 +88,7 @@ fn dispatch_impl(expr: &mut Expr, target: Option<&Target>) -> Result<()> {
 impl VisitMut for StaticDispatchVisitor<'_> {
     fn visit_expr_mut(&mut self, i: &mut Expr) {
         if self.status.as_ref().ok().is_some() {
-            if let Err(error) = dispatch_impl(i, self.target) {
+            if let Err(error) = dispatch_impl(i, self.target, self.crate_path) {
                 self.status = Err(error);
             }
             visit_mut::visit_expr_mut(self, i);
@@ -68,8 +96,12 @@ impl VisitMut for StaticDispatchVisitor<'_> {
     }
 }
 
-pub(crate) fn process_static_dispatch(item: &mut ItemFn, target: Option<&Target>) -> Result<()> {
-    let mut visitor = StaticDispatchVisitor::new(target);
-    visitor.visit_item_fn_mut(item);
+pub(crate) fn process_static_dispatch(
+    block: &mut Block,
+    target: Option<&Target>,
+    crate_path: &Path,
+) -> Result<()> {
+    let mut visitor = StaticDispatchVisitor::new(target, crate_path);
+    visitor.visit_block_mut(block);
     visitor.status()
 }
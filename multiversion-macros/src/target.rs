@@ -1,15 +1,49 @@
+use crate::features::{self, Cpu, Level};
+use crate::meta::{parse_attributes, parse_crate_path};
 use crate::safe_inner::process_safe_inner;
 use crate::static_dispatch::process_static_dispatch;
 use crate::target_cfg::process_target_cfg;
 use proc_macro2::TokenStream;
 use quote::quote;
+use std::collections::BTreeSet;
 use std::convert::TryInto;
 use syn::{parse_quote, Attribute, Error, ItemFn, Lit, LitStr, Path, Result};
 
+const UNCHECKED_PREFIX: &str = "unchecked:";
+
+// Checks that a feature is known on every architecture it's specified for
+fn validate_feature(s: &LitStr, architectures: &[String], feature: &str) -> Result<()> {
+    for arch in architectures {
+        let known = if let Some(known) = features::known_features(arch) {
+            known
+        } else {
+            continue;
+        };
+        if !known.contains(&feature) {
+            let message = if let Some(suggestion) = features::suggest_feature(arch, feature) {
+                format!(
+                    "unknown feature `{}` for architecture `{}`, did you mean `{}`?",
+                    feature, arch, suggestion
+                )
+            } else {
+                format!(
+                    "unknown feature `{}` for architecture `{}` (features unknown to multiversion may be specified as `{}{}`)",
+                    feature, arch, UNCHECKED_PREFIX, feature
+                )
+            };
+            return Err(Error::new(s.span(), message));
+        }
+    }
+    Ok(())
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct Target {
+    specification: String,
     architectures: Vec<String>,
     features: Vec<String>,
+    level: Option<&'static Level>,
+    cpu: Option<&'static Cpu>,
     span: proc_macro2::Span,
 }
 
@@ -29,7 +63,10 @@ impl Target {
             .next()
             .filter(|x| !x.is_empty())
             .ok_or_else(|| Error::new(s.span(), "expected architecture specifier"))?;
-        let architectures = if arch_specifier.starts_with('[') && arch_specifier.ends_with(']') {
+        let level = features::level(arch_specifier);
+        let architectures = if let Some(level) = level {
+            vec![level.arch.to_string()]
+        } else if arch_specifier.starts_with('[') && arch_specifier.ends_with(']') {
             arch_specifier[1..arch_specifier.len() - 1]
                 .split('|')
                 .map(|x| {
@@ -51,29 +88,169 @@ impl Target {
 
         let mut features = it
             .map(|x| {
-                if x.is_empty() {
-                    Err(Error::new(s.span(), "feature string cannot be empty"))
-                } else {
-                    Ok(x.to_string())
+                // Features prefixed with `unchecked:` are not validated
+                let (feature, checked) = match x.strip_prefix(UNCHECKED_PREFIX) {
+                    Some(feature) => (feature, false),
+                    None => (x, true),
+                };
+                if feature.is_empty() {
+                    return Err(Error::new(s.span(), "feature string cannot be empty"));
+                }
+                if checked {
+                    validate_feature(s, &architectures, feature)?;
                 }
+                Ok(feature.to_string())
             })
             .collect::<Result<Vec<_>>>()?;
-        features.sort_unstable();
-        features.dedup();
+        if let Some(level) = level {
+            features.extend(level.features.iter().map(ToString::to_string));
+        }
 
-        Ok(Self {
+        let mut target = Self {
+            specification: value.clone(),
             architectures,
             features,
+            level,
+            cpu: None,
             span: s.span(),
+        };
+        target.normalize();
+        Ok(target)
+    }
+
+    // Returns the features and the features they imply on every architecture of this target.
+    fn implied_features<'a>(&self, features: &[&'a str]) -> BTreeSet<&'a str> {
+        let mut arches = self.arches();
+        let first = arches.next().map_or_else(BTreeSet::new, |arch| {
+            features::implied_features(arch, features)
+        });
+        arches.fold(first, |implied, arch| {
+            implied
+                .intersection(&features::implied_features(arch, features))
+                .copied()
+                .collect()
         })
     }
 
+    // Expands the features to include all implied features, so that targets with equivalent
+    // features compare equal, and selects the highest microarchitecture level that is enabled.
+    fn normalize(&mut self) {
+        let features = self.features.iter().map(String::as_str).collect::<Vec<_>>();
+        self.features = self
+            .implied_features(&features)
+            .into_iter()
+            .map(ToString::to_string)
+            .collect();
+
+        let arches = self.arches().collect::<Vec<_>>();
+        self.level = features::levels()
+            .rev()
+            .find(|level| {
+                arches == [level.arch]
+                    && level
+                        .features
+                        .iter()
+                        .all(|feature| self.features.iter().any(|f| f == feature))
+            })
+            .or(self.level);
+    }
+
+    // Returns the features not implied by any other feature in the set.
+    fn minimal_features<'a>(&self, features: &[&'a str]) -> Vec<&'a str> {
+        features
+            .iter()
+            .copied()
+            .filter(|feature| {
+                !features.iter().any(|other| {
+                    other != feature && self.implied_features(&[other]).contains(feature)
+                })
+            })
+            .collect()
+    }
+
+    /// Specifies a CPU model for this target, adding the features supported by the CPU.
+    pub(crate) fn set_cpu(&mut self, s: &LitStr) -> Result<()> {
+        let name = s.value();
+        let cpu = features::cpu(&name).ok_or_else(|| Error::new(s.span(), "unknown cpu model"))?;
+        if let Some(arch) = self.arches().find(|arch| !cpu.arches.contains(arch)) {
+            return Err(Error::new(
+                s.span(),
+                format!("cpu `{}` is not supported on architecture `{}`", name, arch),
+            ));
+        }
+        self.features
+            .extend(cpu.all_features().into_iter().map(ToString::to_string));
+        self.cpu = Some(cpu);
+        self.normalize();
+        Ok(())
+    }
+
     pub fn arches(&self) -> impl Iterator<Item = &str> {
         self.architectures.iter().map(String::as_str)
     }
 
+    pub fn features(&self) -> impl Iterator<Item = &str> {
+        self.features.iter().map(String::as_str)
+    }
+
+    // Checks that every feature can be detected at runtime, for targets selected by a dispatcher
+    pub fn check_detectable(&self) -> Result<()> {
+        for arch in self.arches() {
+            if let Some(feature) = self
+                .features()
+                .find(|feature| !features::is_detectable(arch, feature))
+            {
+                return Err(Error::new(
+                    self.span,
+                    format!(
+                        "feature `{}` can't be detected at runtime on architecture `{}`, so it can only be used with `#[target]` and `#[target_cfg]`",
+                        feature, arch
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
     pub fn features_string(&self) -> String {
-        self.features.join("_").replace(".", "")
+        // Features provided by a CPU model or named level are represented by the CPU or level
+        // name, and features implied by other features are omitted
+        let mut parts = Vec::new();
+        let base_features = if let Some(cpu) = self.cpu {
+            parts.push(cpu.mangled());
+            self.implied_features(&cpu.all_features())
+        } else if let Some(level) = self.level {
+            parts.push(level.mangled.to_string());
+            self.implied_features(level.features)
+        } else {
+            BTreeSet::new()
+        };
+        let features = self
+            .features
+            .iter()
+            .map(String::as_str)
+            .filter(|f| !base_features.contains(f))
+            .collect::<Vec<_>>();
+        parts.extend(
+            self.minimal_features(&features)
+                .into_iter()
+                .map(ToString::to_string),
+        );
+        // Feature names may contain characters that aren't valid in identifiers
+        parts
+            .join("_")
+            .replace('.', "")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect()
+    }
+
+    pub fn specification(&self) -> &str {
+        &self.specification
+    }
+
+    pub fn cpu(&self) -> Option<&str> {
+        self.cpu.map(|cpu| cpu.name)
     }
 
     pub fn has_features_specified(&self) -> bool {
@@ -87,9 +264,30 @@ impl Target {
         }
     }
 
+    // Returns the condition under which every feature of the target is enabled during compilation,
+    // so the target is always supported, or `None` if the target has no features or features
+    // unknown to multiversion.
+    pub fn features_enabled(&self) -> Option<TokenStream> {
+        let known = self.arches().all(|arch| {
+            features::known_features(arch).map_or(false, |known| {
+                self.features().all(|feature| known.contains(&feature))
+            })
+        });
+        if self.features.is_empty() || !known {
+            return None;
+        }
+        let arch = self.arches();
+        let features = self.features();
+        Some(quote! {
+            all(any(#(target_arch = #arch),*), #(target_feature = #features),*)
+        })
+    }
+
     pub fn target_feature(&self) -> Vec<Attribute> {
-        self.features
-            .iter()
+        // Implied features are enabled by rustc, so only the minimal set is necessary
+        let features = self.features.iter().map(String::as_str).collect::<Vec<_>>();
+        self.minimal_features(&features)
+            .into_iter()
             .map(|feature| {
                 parse_quote! {
                     #[target_feature(enable = #feature)]
@@ -98,14 +296,107 @@ impl Target {
             .collect()
     }
 
+    // Returns the bitmask of detected features required by the target, if it's the same on every
+    // architecture of the target
+    fn feature_mask(&self) -> Option<u64> {
+        let features = self.features().collect::<Vec<_>>();
+        let mut masks = self
+            .arches()
+            .map(|arch| features::feature_mask(arch, &features));
+        let mask = masks.next()??;
+        if masks.all(|other| other == Some(mask)) {
+            Some(mask)
+        } else {
+            None
+        }
+    }
+
     pub fn features_detected(&self, crate_path: &Path) -> TokenStream {
         if self.features.is_empty() {
             quote! { true }
+        } else if let (true, Some(mask)) = (cfg!(feature = "std"), self.feature_mask()) {
+            let features = &self.features;
+            quote! {
+                (#crate_path::CpuFeatures::detected().__contains_mask(#mask)
+                    && #crate_path::__private::dispatch_allowed(&[#(#features),*]))
+            }
+        } else if cfg!(feature = "std") {
+            let features = &self.features;
+            quote! {
+                (#crate_path::__private::dispatch_allowed(&[#(#features),*])
+                    && match #crate_path::__private::detected_by_detector(&[#(#features),*]) {
+                        Some(detected) => detected,
+                        None => #crate_path::are_cpu_features_detected!(#(#features),*),
+                    })
+            }
         } else {
             let features = &self.features;
-            quote! { #crate_path::are_cpu_features_detected!(#(#features),*) }
+            quote! {
+                (#crate_path::__private::dispatch_allowed(&[#(#features),*])
+                    && #crate_path::are_cpu_features_detected!(#(#features),*))
+            }
+        }
+    }
+}
+
+// Returns the first architecture shared by both targets
+fn shared_arch<'a>(a: &'a Target, b: &Target) -> Option<&'a str> {
+    a.arches()
+        .find(|arch| b.arches().any(|other| other == *arch))
+}
+
+// Returns true if the first target has every feature of the second, and more
+fn is_superset(a: &Target, b: &Target) -> bool {
+    a.features.len() > b.features.len()
+        && b.features()
+            .all(|feature| a.features.contains(&feature.to_string()))
+}
+
+// Checks that every target can be selected, since targets are selected in the order they are
+// specified.  A target is rejected if an earlier target on the same architecture has a subset of
+// its features, since the earlier target would always be selected first, or if, on every
+// architecture it supports, an earlier target has the same features.
+pub(crate) fn check_target_order<T>(items: &[T], target: impl Fn(&T) -> &Target) -> Result<()> {
+    for (index, item) in items.iter().enumerate() {
+        let later = target(item);
+        let earlier = items[..index].iter().map(&target);
+        if let Some((earlier, arch)) = earlier.clone().find_map(|earlier| {
+            shared_arch(later, earlier)
+                .filter(|_| is_superset(later, earlier))
+                .map(|arch| (earlier, arch))
+        }) {
+            return Err(Error::new(
+                later.span,
+                format!(
+                    "target `{}` has every feature of the earlier target `{}` on `{}`, so it would never be selected; specify it first",
+                    later.specification, earlier.specification, arch
+                ),
+            ));
+        }
+        let covering = later
+            .arches()
+            .map(|arch| {
+                earlier
+                    .clone()
+                    .find(|earlier| {
+                        earlier.arches().any(|other| other == arch)
+                            && earlier.features == later.features
+                    })
+                    .map(|earlier| format!("`{}` on `{}`", earlier.specification, arch))
+            })
+            .collect::<Option<Vec<_>>>();
+        if let Some(covering) = covering {
+            return Err(Error::new(
+                later.span,
+                format!(
+                    "target `{}` has the same features as {}, so it would never be selected",
+                    later.specification,
+                    covering.join(" and ")
+                ),
+            ));
         }
     }
+    Ok(())
 }
 
 impl std::convert::TryFrom<&Lit> for Target {
@@ -119,19 +410,32 @@ impl std::convert::TryFrom<&Lit> for Target {
     }
 }
 
-pub(crate) fn make_target_fn(target: Option<Lit>, func: ItemFn) -> Result<TokenStream> {
+pub(crate) fn make_target_fn(target: Option<Lit>, mut func: ItemFn) -> Result<TokenStream> {
     let target = target.as_ref().map(|s| s.try_into()).transpose()?;
-    let functions = make_target_fn_items(target.as_ref(), func)?;
+    let mut crate_path = parse_quote!(multiversion);
+    func.attrs = parse_attributes(func.attrs.drain(..), |path, nested| {
+        Ok(if path == "crate_path" {
+            crate_path = parse_crate_path(nested)?;
+            true
+        } else {
+            false
+        })
+    })?;
+    let functions = make_target_fn_items(target.as_ref(), func, &crate_path, None)?;
     Ok(quote! { #(#functions)* })
 }
 
+// The path prefix of associated functions may be specified, otherwise `Self::` is used if the
+// function is associated
 pub(crate) fn make_target_fn_items(
     target: Option<&Target>,
     mut func: ItemFn,
+    crate_path: &Path,
+    maybe_self: Option<&TokenStream>,
 ) -> Result<Vec<ItemFn>> {
     // Rewrite #[target_cfg] and #[static_dispatch]
     process_target_cfg(target.cloned(), &mut func.block)?;
-    process_static_dispatch(&mut func, target)?;
+    process_static_dispatch(&mut func.block, target, crate_path)?;
 
     // Create the function
     if let Some(target) = target {
@@ -139,7 +443,7 @@ pub(crate) fn make_target_fn_items(
         let target_feature = target.target_feature();
         func = parse_quote! { #target_arch #(#target_feature)* #func };
     }
-    process_safe_inner(func)
+    process_safe_inner(func, maybe_self)
 }
 
 #[cfg(test)]
@@ -152,7 +456,7 @@ mod test {
         let s = LitStr::new("x86", Span::call_site());
         let target = Target::parse(&s).unwrap();
         assert_eq!(target.architectures, vec!["x86"]);
-        assert_eq!(target.features.is_empty(), true);
+        assert!(target.features.is_empty());
     }
 
     #[test]
@@ -171,15 +475,279 @@ mod test {
         let s = LitStr::new("x86_64+sse4.2+xsave", Span::call_site());
         let target = Target::parse(&s).unwrap();
         assert_eq!(target.architectures, vec!["x86_64"]);
-        assert_eq!(target.features, vec!["sse4.2", "xsave"]);
+        assert_eq!(
+            target.features,
+            vec!["sse", "sse2", "sse3", "sse4.1", "sse4.2", "ssse3", "xsave"]
+        );
     }
 
     #[test]
     fn parse_multiple_arch_with_features() {
-        let s = LitStr::new("[powerpc|powerpc64]+altivec+power8", Span::call_site());
+        let s = LitStr::new("[powerpc|powerpc64]+altivec+vsx", Span::call_site());
         let target = Target::parse(&s).unwrap();
         assert_eq!(target.architectures, vec!["powerpc", "powerpc64"]);
-        assert_eq!(target.features, vec!["altivec", "power8"]);
+        assert_eq!(target.features, vec!["altivec", "vsx"]);
+    }
+
+    #[test]
+    fn parse_level() {
+        let s = LitStr::new("x86_64-v2", Span::call_site());
+        let target = Target::parse(&s).unwrap();
+        assert_eq!(target.architectures, vec!["x86_64"]);
+        assert_eq!(
+            target.features,
+            vec![
+                "cmpxchg16b",
+                "popcnt",
+                "sse",
+                "sse2",
+                "sse3",
+                "sse4.1",
+                "sse4.2",
+                "ssse3"
+            ]
+        );
+        assert_eq!(target.features_string(), "x86_64_v2");
+    }
+
+    #[test]
+    fn parse_level_with_features() {
+        let s = LitStr::new("x86-64-v3+aes+avx2", Span::call_site());
+        let target = Target::parse(&s).unwrap();
+        assert_eq!(target.architectures, vec!["x86_64"]);
+        assert!(target.features.iter().any(|f| f == "aes"));
+        assert!(target.features.iter().any(|f| f == "fma"));
+        assert_eq!(target.features_string(), "x86_64_v3_aes");
+        assert_eq!(
+            target,
+            Target::parse(&LitStr::new("x86_64-v3+aes", Span::call_site())).unwrap()
+        );
+    }
+
+    #[test]
+    fn feature_mask() {
+        let parse = |s| Target::parse(&LitStr::new(s, Span::call_site())).unwrap();
+        let sse2 = parse("[x86|x86_64]+sse2").feature_mask().unwrap();
+        let avx = parse("[x86|x86_64]+avx").feature_mask().unwrap();
+        assert_eq!(sse2.count_ones(), 2);
+        assert_eq!(avx & sse2, sse2);
+        assert!(parse("x86_64+xsaves").feature_mask().unwrap() < 1 << 63);
+        assert!(parse("aarch64+sve2").feature_mask().is_some());
+        assert!(parse("[x86|x86_64|aarch64]+aes").feature_mask().is_none());
+        assert!(parse("x86_64+unchecked:newfeature")
+            .feature_mask()
+            .is_none());
+        assert!(parse("[powerpc|powerpc64]+vsx").feature_mask().is_none());
+        assert!(parse("[riscv32|riscv64]+v+zba+zbb")
+            .feature_mask()
+            .is_some());
+        assert_eq!(
+            parse("loongarch64+lasx")
+                .feature_mask()
+                .unwrap()
+                .count_ones(),
+            2
+        );
+        assert!(parse("loongarch64+lvz").feature_mask().is_none());
+        assert!(parse("s390x+vector").feature_mask().is_some());
+        assert!(parse("s390x+vector-enhancements-1")
+            .feature_mask()
+            .is_none());
+    }
+
+    #[test]
+    fn features_enabled() {
+        let parse = |s| Target::parse(&LitStr::new(s, Span::call_site())).unwrap();
+        assert_eq!(
+            parse("[x86|x86_64]+sse2")
+                .features_enabled()
+                .unwrap()
+                .to_string(),
+            quote! {
+                all(
+                    any(target_arch = "x86", target_arch = "x86_64"),
+                    target_feature = "sse",
+                    target_feature = "sse2"
+                )
+            }
+            .to_string()
+        );
+        assert!(parse("x86_64").features_enabled().is_none());
+        assert!(parse("x86_64+unchecked:avx10.1")
+            .features_enabled()
+            .is_none());
+        assert!(parse("sparc64+vis").features_enabled().is_none());
+    }
+
+    #[test]
+    fn check_order_by_features() {
+        let parse = |s: &str| Target::parse(&LitStr::new(s, Span::call_site())).unwrap();
+        let check = |targets: &[&str]| {
+            check_target_order(
+                &targets.iter().map(|s| parse(s)).collect::<Vec<_>>(),
+                |target| target,
+            )
+        };
+        assert!(check(&[
+            "[x86|x86_64]+avx2",
+            "aarch64+neon",
+            "x86_64+avx",
+            "[x86|x86_64]+sse4.1"
+        ])
+        .is_ok());
+        assert!(check(&["[x86|x86_64]+sse4.1", "aarch64+neon", "[x86|x86_64]+avx2"]).is_err());
+        assert!(check(&["x86_64+avx", "x86+avx2+fma", "[x86|x86_64]+avx2"]).is_err());
+        assert!(check(&["x86+avx2+fma", "[x86|x86_64]+avx2", "x86_64+avx"]).is_ok());
+        assert!(check(&["x86_64", "x86_64+sse4.2"]).is_err());
+        assert!(check(&["x86+avx", "x86_64+avx2"]).is_ok());
+        assert!(check(&["[x86|x86_64]+avx", "x86_64+avx"]).is_err());
+        assert!(check(&["x86_64+avx", "[x86|x86_64]+avx"]).is_ok());
+        assert!(check(&["x86+avx", "x86_64+avx", "[x86|x86_64]+avx"]).is_err());
+        assert!(check(&["x86+avx", "x86_64+avx2", "[x86|x86_64]+avx"]).is_ok());
+        assert!(check(&["x86_64+avx2", "x86_64+avx+avx2"]).is_err());
+        assert!(check(&["x86_64", "x86_64"]).is_err());
+    }
+
+    #[test]
+    fn other_architectures() {
+        let parse = |s| Target::parse(&LitStr::new(s, Span::call_site()));
+        let lasx = parse("loongarch64+lasx").unwrap();
+        assert!(lasx.features().any(|f| f == "lsx"));
+        assert_eq!(lasx.features_string(), "lasx");
+        assert!(parse("riscv64+v").unwrap().features().any(|f| f == "d"));
+        assert!(parse("s390x+vector-enhancements-2")
+            .unwrap()
+            .features()
+            .any(|f| f == "vector"));
+        assert!(parse("riscv64+zbbb").is_err());
+    }
+
+    #[test]
+    fn dashed_features_string() {
+        let parse = |s| Target::parse(&LitStr::new(s, Span::call_site())).unwrap();
+        assert_eq!(parse("aarch64+sve2-aes").features_string(), "sve2_aes");
+        assert_eq!(
+            parse("powerpc64+power8-altivec").features_string(),
+            "power8_altivec"
+        );
+        assert_eq!(
+            parse("x86_64+unchecked:avx10-512").features_string(),
+            "avx10_512"
+        );
+    }
+
+    #[test]
+    fn normalize_implied_features() {
+        let avx2 = Target::parse(&LitStr::new("x86_64+avx2", Span::call_site())).unwrap();
+        let avx_avx2 = Target::parse(&LitStr::new("x86_64+avx+avx2", Span::call_site())).unwrap();
+        assert_eq!(avx2, avx_avx2);
+        assert_eq!(avx2.features_string(), "avx2");
+        assert_eq!(avx_avx2.features_string(), "avx2");
+        assert_eq!(
+            avx_avx2.target_feature(),
+            vec![parse_quote! { #[target_feature(enable = "avx2")] }]
+        );
+
+        let target = Target::parse(&LitStr::new("[x86|x86_64]+sse4.1+avx", Span::call_site()));
+        assert_eq!(target.unwrap().features_string(), "avx");
+    }
+
+    #[test]
+    fn normalize_implied_features_multiple_arch() {
+        // aes implies neon on aarch64, but not on arm
+        let target = Target::parse(&LitStr::new("[arm|aarch64]+aes", Span::call_site())).unwrap();
+        assert_eq!(target.features, vec!["aes"]);
+        let target = Target::parse(&LitStr::new("aarch64+aes", Span::call_site())).unwrap();
+        assert_eq!(target.features, vec!["aes", "neon"]);
+    }
+
+    #[test]
+    fn normalize_level() {
+        let s = LitStr::new(
+            "x86_64+avx2+bmi1+bmi2+cmpxchg16b+f16c+fma+lzcnt+movbe+popcnt+xsave",
+            Span::call_site(),
+        );
+        let target = Target::parse(&s).unwrap();
+        assert_eq!(target.features_string(), "x86_64_v3");
+        assert_eq!(
+            target,
+            Target::parse(&LitStr::new("x86_64-v3", Span::call_site())).unwrap()
+        );
+    }
+
+    #[test]
+    fn cpu() {
+        let mut target = Target::parse(&LitStr::new("x86_64+aes", Span::call_site())).unwrap();
+        target
+            .set_cpu(&LitStr::new("haswell", Span::call_site()))
+            .unwrap();
+        assert!(target.features.iter().any(|f| f == "avx2"));
+        assert!(target.features.iter().any(|f| f == "sse4.2"));
+        assert_eq!(target.cpu(), Some("haswell"));
+        assert_eq!(target.features_string(), "haswell");
+
+        let mut target = Target::parse(&LitStr::new("aarch64", Span::call_site())).unwrap();
+        target
+            .set_cpu(&LitStr::new("neoverse-n1", Span::call_site()))
+            .unwrap();
+        assert_eq!(target.features_string(), "neoverse_n1");
+    }
+
+    #[test]
+    fn cpu_wrong_arch() {
+        let mut target =
+            Target::parse(&LitStr::new("[x86_64|aarch64]", Span::call_site())).unwrap();
+        target
+            .set_cpu(&LitStr::new("znver3", Span::call_site()))
+            .unwrap_err();
+        target
+            .set_cpu(&LitStr::new("not-a-cpu", Span::call_site()))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn unknown_feature() {
+        let s = LitStr::new("x86_64+avx3", Span::call_site());
+        let message = Target::parse(&s).unwrap_err().to_string();
+        assert!(message.contains("did you mean `avx2`?"), "{}", message);
+
+        let s = LitStr::new("[arm|aarch64]+neno", Span::call_site());
+        let message = Target::parse(&s).unwrap_err().to_string();
+        assert!(message.contains("did you mean `neon`?"), "{}", message);
+
+        // sve is not an arm feature
+        let s = LitStr::new("[arm|aarch64]+sve", Span::call_site());
+        let message = Target::parse(&s).unwrap_err().to_string();
+        assert!(message.contains("architecture `arm`"), "{}", message);
+
+        let s = LitStr::new("x86_64+qwertyuiop", Span::call_site());
+        let message = Target::parse(&s).unwrap_err().to_string();
+        assert!(message.contains("unchecked:qwertyuiop"), "{}", message);
+    }
+
+    #[test]
+    fn unchecked_feature() {
+        let s = LitStr::new("x86_64+avx2+unchecked:avx10.1", Span::call_site());
+        let target = Target::parse(&s).unwrap();
+        assert!(target.features.iter().any(|f| f == "avx10.1"));
+        assert_eq!(target.features_string(), "avx101_avx2");
+
+        // features of architectures without a feature database are not checked
+        assert!(features::known_features("sparc64").is_none());
+        let s = LitStr::new("sparc64+vis", Span::call_site());
+        Target::parse(&s).unwrap();
+    }
+
+    #[test]
+    fn undetectable_feature() {
+        let target = Target::parse(&LitStr::new("aarch64+neon+lor", Span::call_site())).unwrap();
+        let message = target.check_detectable().unwrap_err().to_string();
+        assert!(message.contains("feature `lor`"), "{}", message);
+
+        let target = Target::parse(&LitStr::new("[arm|aarch64]+neon", Span::call_site())).unwrap();
+        target.check_detectable().unwrap();
+        let target = Target::parse(&LitStr::new("arm+neon+v7", Span::call_site())).unwrap();
+        target.check_detectable().unwrap_err();
     }
 
     #[test]
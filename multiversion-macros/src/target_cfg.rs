@@ -31,6 +31,13 @@ impl ReplaceTargetCfg {
                         } else {
                             Err(Error::new(lit.span(), "expected string literal"))
                         }
+                    } else if path.is_ident("cpu") {
+                        if let Lit::Str(s) = lit {
+                            let cpu = self.target.as_ref().and_then(Target::cpu);
+                            Ok(cpu == Some(s.value().as_str()))
+                        } else {
+                            Err(Error::new(lit.span(), "expected string literal"))
+                        }
                     } else {
                         Err(Error::new(path.span(), "unknown key"))
                     }
@@ -0,0 +1,52 @@
+use crate::target::Target;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    bracketed,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    token::{Bracket, Comma},
+    Ident, LitStr, Result, Token, Visibility,
+};
+
+pub(crate) struct TargetSet {
+    vis: Visibility,
+    name: Ident,
+    targets: Punctuated<LitStr, Comma>,
+}
+
+impl Parse for TargetSet {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let vis = input.parse()?;
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let content;
+        let _: Bracket = bracketed!(content in input);
+        let targets = content.parse_terminated(|input| input.parse::<LitStr>())?;
+        Ok(Self { vis, name, targets })
+    }
+}
+
+pub(crate) fn make_target_set(set: TargetSet) -> Result<TokenStream> {
+    // Check the targets when the set is defined, rather than where it's used
+    for target in &set.targets {
+        Target::parse(target)?;
+    }
+
+    let TargetSet { vis, name, targets } = set;
+    let targets = targets.iter();
+    let macro_name = format_ident!("__multiversion_target_set_{}", name);
+    Ok(quote! {
+        #[doc(hidden)]
+        #[allow(unused_macros)]
+        macro_rules! #macro_name {
+            { $multiversion:meta, $($item:tt)* } => {
+                #[$multiversion]
+                #(#[clone(target = #targets)])*
+                $($item)*
+            }
+        }
+        #[allow(unused_imports)]
+        #vis use #macro_name as #name;
+    })
+}
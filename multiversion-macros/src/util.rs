@@ -1,9 +1,11 @@
 use proc_macro2::{Span, TokenStream};
 use quote::quote;
 use syn::{
-    parse_quote, spanned::Spanned, visit_mut::VisitMut, BareFnArg, Error, Expr, FnArg,
-    GenericParam, Ident, Item, ItemFn, Lifetime, Pat, PatIdent, PatType, Result, ReturnType,
-    Signature, Type, TypeBareFn,
+    parse_quote,
+    spanned::Spanned,
+    visit_mut::{self, VisitMut},
+    Attribute, BareFnArg, Expr, FnArg, GenericParam, Ident, Item, ItemFn, Lifetime, Pat, PatIdent,
+    PatType, Receiver, ReturnType, Signature, Type, TypeBareFn, TypePath,
 };
 
 struct HasSelfType(bool);
@@ -27,6 +29,43 @@ pub(crate) fn is_associated_fn(item: &mut ItemFn) -> bool {
     v.0
 }
 
+struct AssociatedTypeReplacer<'a>(&'a [(Ident, Type)]);
+
+impl VisitMut for AssociatedTypeReplacer<'_> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        if let Type::Path(TypePath { qself: None, path }) = ty {
+            if path.segments.len() == 2
+                && path.segments[0].ident == "Self"
+                && path.segments.iter().all(|s| s.arguments.is_empty())
+            {
+                if let Some((_, replacement)) = self
+                    .0
+                    .iter()
+                    .find(|(ident, _)| *ident == path.segments[1].ident)
+                {
+                    *ty = replacement.clone();
+                    return;
+                }
+            }
+        }
+        visit_mut::visit_type_mut(self, ty);
+    }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {
+        // Nested items may have their own `Self` type
+    }
+}
+
+// Replaces `Self::Type` with the associated type's definition, since associated types of traits
+// can't be referred to this way outside of the trait implementation
+pub(crate) fn replace_associated_types(item: &mut ItemFn, types: &[(Ident, Type)]) {
+    AssociatedTypeReplacer(types).visit_item_fn_mut(item);
+}
+
+pub(crate) fn is_inline_attr(attr: &Attribute) -> bool {
+    attr.path.is_ident("inline")
+}
+
 pub(crate) fn arg_exprs(sig: &Signature) -> Vec<Expr> {
     sig.inputs
         .iter()
@@ -53,6 +92,13 @@ pub(crate) fn normalize_signature(sig: &Signature) -> (Signature, Vec<Expr>) {
         .iter()
         .enumerate()
         .map(|(i, x)| match x {
+            // The receiver is passed on, so it doesn't need to be mutable
+            FnArg::Receiver(receiver) if receiver.reference.is_none() => {
+                FnArg::Receiver(Receiver {
+                    mutability: None,
+                    ..receiver.clone()
+                })
+            }
             FnArg::Receiver(_) => x.clone(),
             FnArg::Typed(arg) => FnArg::Typed(PatType {
                 pat: Box::new(Pat::Ident(PatIdent {
@@ -97,29 +143,33 @@ struct LifetimeRenamer;
 
 impl VisitMut for LifetimeRenamer {
     fn visit_lifetime_mut(&mut self, i: &mut Lifetime) {
-        i.ident = Ident::new(&format!("__mv_inner_{}", i.ident), i.ident.span());
+        // The static and anonymous lifetimes are never declared
+        if i.ident != "static" && i.ident != "_" {
+            i.ident = Ident::new(&format!("__mv_inner_{}", i.ident), i.ident.span());
+        }
     }
 }
 
-pub(crate) fn fn_type_from_signature(sig: &Signature) -> Result<TypeBareFn> {
+pub(crate) fn fn_type_from_signature(sig: &Signature) -> TypeBareFn {
     let lifetimes = sig.generics.lifetimes().collect::<Vec<_>>();
     let args = sig
         .inputs
         .iter()
-        .map(|x| {
-            Ok(BareFnArg {
-                attrs: Vec::new(),
-                name: None,
-                ty: match x {
-                    FnArg::Receiver(rec) => Err(Error::new(
-                        rec.self_token.span,
-                        "cannot determine type of associated fn",
-                    )),
-                    FnArg::Typed(arg) => Ok(arg.ty.as_ref().clone()),
-                }?,
-            })
+        .map(|x| BareFnArg {
+            attrs: Vec::new(),
+            name: None,
+            ty: match x {
+                FnArg::Receiver(rec) => {
+                    let mutability = &rec.mutability;
+                    match &rec.reference {
+                        Some((_, lifetime)) => parse_quote! { &#lifetime #mutability Self },
+                        None => parse_quote! { Self },
+                    }
+                }
+                FnArg::Typed(arg) => arg.ty.as_ref().clone(),
+            },
         })
-        .collect::<Result<Vec<_>>>()?;
+        .collect::<Vec<_>>();
     let mut fn_ty = TypeBareFn {
         lifetimes: if lifetimes.is_empty() {
             None
@@ -135,7 +185,7 @@ pub(crate) fn fn_type_from_signature(sig: &Signature) -> Result<TypeBareFn> {
         output: sig.output.clone(),
     };
     LifetimeRenamer {}.visit_type_bare_fn_mut(&mut fn_ty);
-    Ok(fn_ty)
+    fn_ty
 }
 
 pub(crate) fn fn_params(sig: &Signature) -> Vec<Ident> {
@@ -0,0 +1,59 @@
+//! Utilities for benchmarking every version of multiversioned functions with [criterion].
+//!
+//! This module requires the `criterion` cargo feature.
+//!
+//! [criterion]: https://docs.rs/criterion
+
+use crate::Version;
+use criterion::{Bencher, BenchmarkId, Criterion};
+
+/// Benchmarks every version supported by the CPU in a benchmark group named `name`.
+///
+/// The versions are typically the `{IDENT}_VERSIONS` constant created by [`multiversion`].  Each
+/// version is benchmarked by `routine`, which is passed the version's function pointer and
+/// `input`, and is identified by its target.  Unsupported versions are skipped, so `routine` may
+/// safely call the function pointer.
+///
+/// ```no_run
+/// use criterion::{criterion_group, criterion_main, Criterion};
+///
+/// #[multiversion::multiversion]
+/// #[clone(target = "[x86|x86_64]+avx")]
+/// #[clone(target = "x86+sse")]
+/// fn square(x: &mut [f32]) {
+///     for v in x {
+///         *v *= *v
+///     }
+/// }
+///
+/// fn bench(c: &mut Criterion) {
+///     let input = vec![2f32; 1000];
+///     multiversion::bench::bench_all_versions(c, "square", SQUARE_VERSIONS, &input, |b, f, i| {
+///         let mut x = i.clone();
+///         b.iter(|| unsafe { f(&mut x) })
+///     });
+/// }
+///
+/// criterion_group!(benches, bench);
+/// criterion_main!(benches);
+/// ```
+///
+/// [`multiversion`]: ../attr.multiversion.html
+pub fn bench_all_versions<F: Copy, I: ?Sized>(
+    c: &mut Criterion,
+    name: &str,
+    versions: &[(Version, F)],
+    input: &I,
+    mut routine: impl FnMut(&mut Bencher, F, &I),
+) {
+    let mut group = c.benchmark_group(name);
+    for (version, f) in versions
+        .iter()
+        .filter(|(version, _)| version.is_supported())
+    {
+        group.bench_with_input(BenchmarkId::from_parameter(version), input, |b, input| {
+            routine(b, *f, input)
+        });
+    }
+    group.finish();
+}
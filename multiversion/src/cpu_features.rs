@@ -0,0 +1,94 @@
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+mod tables {
+    multiversion_macros::__cpu_features!();
+}
+
+// Set once the features have been detected, so zero can't be mistaken for detected features
+const DETECTED: u64 = 1 << 63;
+
+static FEATURES: AtomicU64 = AtomicU64::new(0);
+
+/// A set of CPU features detected at runtime.
+///
+/// The features are detected once, the first time [`detected`] is called, and cached in a single
+/// bitmask.  Dispatchers check the features required by each version against this bitmask, rather
+/// than detecting each feature separately.  Features that can't be cached in the bitmask, such as
+/// features for architectures without runtime detection, are never contained in the set.
+///
+/// If a [`Detector`] is installed, the features are detected with it instead of the CPU.
+///
+/// This type requires the `std` cargo feature.
+///
+/// [`detected`]: #method.detected
+/// [`Detector`]: trait.Detector.html
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CpuFeatures(u64);
+
+impl CpuFeatures {
+    /// Returns the features detected on the CPU.
+    #[inline]
+    pub fn detected() -> Self {
+        let features = FEATURES.load(Ordering::Relaxed);
+        if features & DETECTED != 0 {
+            Self(features & !DETECTED)
+        } else {
+            Self::detect()
+        }
+    }
+
+    #[cold]
+    fn detect() -> Self {
+        let features = match crate::detect::detector() {
+            Some(detector) => tables::NAMES
+                .iter()
+                .enumerate()
+                .filter(|(_, name)| detector.is_feature_detected(name))
+                .fold(0, |features, (bit, _)| features | 1 << bit),
+            None => tables::detect(),
+        };
+        FEATURES.store(features | DETECTED, Ordering::Relaxed);
+        Self(features)
+    }
+
+    /// Returns true if the set contains the feature.
+    pub fn contains(&self, feature: &str) -> bool {
+        tables::NAMES
+            .iter()
+            .position(|name| *name == feature)
+            .map_or(false, |bit| self.0 & 1 << bit != 0)
+    }
+
+    /// Returns an iterator over the features in the set.
+    pub fn iter(&self) -> impl Iterator<Item = &'static str> {
+        let features = self.0;
+        tables::NAMES
+            .iter()
+            .enumerate()
+            .filter(move |(bit, _)| features & 1 << bit != 0)
+            .map(|(_, name)| *name)
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn __contains_mask(&self, mask: u64) -> bool {
+        self.0 & mask == mask
+    }
+}
+
+// Returns true if the feature can be contained in the set
+pub(crate) fn is_cached(feature: &str) -> bool {
+    tables::NAMES.contains(&feature)
+}
+
+// Detects the features again, the next time they are used
+pub(crate) fn reset() {
+    FEATURES.store(0, Ordering::Relaxed);
+}
+
+impl fmt::Debug for CpuFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
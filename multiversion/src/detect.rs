@@ -0,0 +1,228 @@
+// Detection of CPU features with an installed detector, and with `cpuid` for indirect functions.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Detects CPU features for dispatchers.
+///
+/// By default, dispatchers detect the features of the CPU running the program.  A detector
+/// installed with [`runtime::set_feature_detector`] replaces this detection for every dispatcher in
+/// the process, such as when running in a sandbox or emulator where native detection is
+/// unreliable, or in tests simulating another CPU.  It's implemented for functions and closures
+/// taking a feature name.  Indirect functions (the `ifunc` argument of `#[multiversion]`) are
+/// resolved before a detector can be installed, so they always detect the CPU's features.
+///
+/// Features are named as in the [`target_feature`] attribute, such as `"avx2"` or `"neon"`.
+///
+/// [`runtime::set_feature_detector`]: runtime/fn.set_feature_detector.html
+/// [`target_feature`]: https://doc.rust-lang.org/reference/attributes/codegen.html#the-target_feature-attribute
+pub trait Detector: Sync {
+    /// Returns true if the feature is detected.
+    fn is_feature_detected(&self, feature: &str) -> bool;
+}
+
+impl<F: Fn(&str) -> bool + Sync> Detector for F {
+    fn is_feature_detected(&self, feature: &str) -> bool {
+        self(feature)
+    }
+}
+
+// The installed detector.  A detector is a wide pointer, so it can't be stored in an atomic, and is
+// instead protected by a spin lock.  The lock is only held to copy the pointer.
+struct Installed {
+    locked: AtomicBool,
+    detector: UnsafeCell<Option<&'static dyn Detector>>,
+}
+
+unsafe impl Sync for Installed {}
+
+impl Installed {
+    fn with<R>(&self, f: impl FnOnce(&mut Option<&'static dyn Detector>) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let result = f(unsafe { &mut *self.detector.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
+
+static INSTALLED: Installed = Installed {
+    locked: AtomicBool::new(false),
+    detector: UnsafeCell::new(None),
+};
+
+pub(crate) fn set_detector(detector: Option<&'static dyn Detector>) {
+    INSTALLED.with(|installed| *installed = detector);
+}
+
+pub(crate) fn detector() -> Option<&'static dyn Detector> {
+    INSTALLED.with(|installed| *installed)
+}
+
+/// Returns whether the installed detector detects every feature, or `None` if no detector is
+/// installed.
+#[doc(hidden)]
+pub fn detected_by_detector(features: &[&str]) -> Option<bool> {
+    detector().map(|detector| {
+        features
+            .iter()
+            .all(|feature| detector.is_feature_detected(feature))
+    })
+}
+
+// Indirect function resolvers detect features with `cpuid`, since they run before the standard
+// library is initialized.
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::{__cpuid_count, _xgetbv, CpuidResult};
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::{__cpuid_count, _xgetbv, CpuidResult};
+
+    #[derive(Copy, Clone)]
+    enum Register {
+        Eax,
+        Ebx,
+        Ecx,
+        Edx,
+    }
+
+    // The state the operating system must save for the feature to be usable
+    #[derive(Copy, Clone, PartialEq)]
+    enum State {
+        None,
+        Xsave,
+        Avx,
+        Avx512,
+    }
+
+    use Register::*;
+
+    // The `cpuid` leaf, subleaf, register, and bit of each feature
+    const CPUID: &[(&str, u32, u32, Register, u32, State)] = &[
+        ("sse3", 1, 0, Ecx, 0, State::None),
+        ("pclmulqdq", 1, 0, Ecx, 1, State::None),
+        ("ssse3", 1, 0, Ecx, 9, State::None),
+        ("fma", 1, 0, Ecx, 12, State::Avx),
+        ("cmpxchg16b", 1, 0, Ecx, 13, State::None),
+        ("sse4.1", 1, 0, Ecx, 19, State::None),
+        ("sse4.2", 1, 0, Ecx, 20, State::None),
+        ("movbe", 1, 0, Ecx, 22, State::None),
+        ("popcnt", 1, 0, Ecx, 23, State::None),
+        ("aes", 1, 0, Ecx, 25, State::None),
+        ("xsave", 1, 0, Ecx, 26, State::Xsave),
+        ("avx", 1, 0, Ecx, 28, State::Avx),
+        ("f16c", 1, 0, Ecx, 29, State::Avx),
+        ("rdrand", 1, 0, Ecx, 30, State::None),
+        ("fxsr", 1, 0, Edx, 24, State::None),
+        ("sse", 1, 0, Edx, 25, State::None),
+        ("sse2", 1, 0, Edx, 26, State::None),
+        ("bmi1", 7, 0, Ebx, 3, State::None),
+        ("avx2", 7, 0, Ebx, 5, State::Avx),
+        ("bmi2", 7, 0, Ebx, 8, State::None),
+        ("avx512f", 7, 0, Ebx, 16, State::Avx512),
+        ("avx512dq", 7, 0, Ebx, 17, State::Avx512),
+        ("rdseed", 7, 0, Ebx, 18, State::None),
+        ("adx", 7, 0, Ebx, 19, State::None),
+        ("avx512ifma", 7, 0, Ebx, 21, State::Avx512),
+        ("avx512cd", 7, 0, Ebx, 28, State::Avx512),
+        ("sha", 7, 0, Ebx, 29, State::None),
+        ("avx512bw", 7, 0, Ebx, 30, State::Avx512),
+        ("avx512vl", 7, 0, Ebx, 31, State::Avx512),
+        ("avx512vbmi", 7, 0, Ecx, 1, State::Avx512),
+        ("avx512vbmi2", 7, 0, Ecx, 6, State::Avx512),
+        ("gfni", 7, 0, Ecx, 8, State::None),
+        ("vaes", 7, 0, Ecx, 9, State::Avx),
+        ("vpclmulqdq", 7, 0, Ecx, 10, State::Avx),
+        ("avx512vnni", 7, 0, Ecx, 11, State::Avx512),
+        ("avx512bitalg", 7, 0, Ecx, 12, State::Avx512),
+        ("avx512vpopcntdq", 7, 0, Ecx, 14, State::Avx512),
+        ("kl", 7, 0, Ecx, 23, State::None),
+        ("avx512vp2intersect", 7, 0, Edx, 8, State::Avx512),
+        ("avx512fp16", 7, 0, Edx, 23, State::Avx512),
+        ("sha512", 7, 1, Eax, 0, State::Avx),
+        ("sm3", 7, 1, Eax, 1, State::Avx),
+        ("sm4", 7, 1, Eax, 2, State::Avx),
+        ("avxvnni", 7, 1, Eax, 4, State::Avx),
+        ("avx512bf16", 7, 1, Eax, 5, State::Avx512),
+        ("avxifma", 7, 1, Eax, 23, State::Avx),
+        ("avxvnniint8", 7, 1, Edx, 4, State::Avx),
+        ("avxneconvert", 7, 1, Edx, 5, State::Avx),
+        ("avxvnniint16", 7, 1, Edx, 10, State::Avx),
+        ("xsaveopt", 0xd, 1, Eax, 0, State::Xsave),
+        ("xsavec", 0xd, 1, Eax, 1, State::Xsave),
+        ("xsaves", 0xd, 1, Eax, 3, State::Xsave),
+        ("widekl", 0x19, 0, Ebx, 2, State::None),
+        ("lzcnt", 0x8000_0001, 0, Ecx, 5, State::None),
+        ("sse4a", 0x8000_0001, 0, Ecx, 6, State::None),
+        ("tbm", 0x8000_0001, 0, Ecx, 21, State::None),
+    ];
+
+    #[allow(unused_unsafe)]
+    fn cpuid(leaf: u32, subleaf: u32) -> CpuidResult {
+        unsafe { __cpuid_count(leaf, subleaf) }
+    }
+
+    #[target_feature(enable = "xsave")]
+    unsafe fn xcr0() -> u64 {
+        _xgetbv(0)
+    }
+
+    pub(super) fn detect(names: &[&str]) -> u64 {
+        let max_leaf = cpuid(0, 0).eax;
+        let max_extended_leaf = cpuid(0x8000_0000, 0).eax;
+
+        // Extended state is only usable if the operating system saves it
+        let osxsave = cpuid(1, 0).ecx & 1 << 27 != 0;
+        let xcr0 = if osxsave { unsafe { xcr0() } } else { 0 };
+        let supported = |state| match state {
+            State::None => true,
+            State::Xsave => osxsave,
+            State::Avx => xcr0 & 0b110 == 0b110,
+            State::Avx512 => xcr0 & 0b1110_0110 == 0b1110_0110,
+        };
+
+        let mut features = 0;
+        for (bit, name) in names.iter().enumerate() {
+            let detected = CPUID.iter().find(|(feature, ..)| feature == name).map_or(
+                false,
+                |(_, leaf, subleaf, register, index, state)| {
+                    let max = if *leaf >= 0x8000_0000 {
+                        max_extended_leaf
+                    } else {
+                        max_leaf
+                    };
+                    if *leaf > max || !supported(*state) {
+                        return false;
+                    }
+                    let result = cpuid(*leaf, *subleaf);
+                    let value = match register {
+                        Eax => result.eax,
+                        Ebx => result.ebx,
+                        Ecx => result.ecx,
+                        Edx => result.edx,
+                    };
+                    value & 1 << index != 0
+                },
+            );
+            if detected {
+                features |= 1 << bit;
+            }
+        }
+        features
+    }
+}
+
+/// Returns true if every feature is detected with `cpuid`, which is safe to use in an indirect
+/// function resolver.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[doc(hidden)]
+pub fn cpuid_features_detected(features: &[&str]) -> bool {
+    features.iter().all(|feature| x86::detect(&[feature]) != 0)
+}
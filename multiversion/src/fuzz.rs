@@ -0,0 +1,277 @@
+//! Utilities for differential fuzzing of multiversioned functions.
+//!
+//! Versions of a function compiled with different features may produce different results, such as
+//! floating point sums that are vectorized in a different order.  These functions call every
+//! version supported by the CPU from a `{IDENT}_VERSIONS` constant created by [`multiversion`] and
+//! compare each output to the output of a baseline version, which is the default version for the
+//! architecture.
+//!
+//! [`find_divergence`] searches a sequence of inputs, such as inputs generated randomly, and
+//! [`assert_agree`] checks a single input, which is suitable for the body of a `cargo fuzz` target
+//! or `proptest` test.
+//!
+//! ```
+//! use multiversion::fuzz;
+//!
+//! #[multiversion::multiversion]
+//! #[clone(target = "[x86|x86_64]+avx")]
+//! #[clone(target = "x86+sse")]
+//! fn sum(x: &[f32]) -> f32 {
+//!     x.iter().sum()
+//! }
+//!
+//! let inputs = (0..100).map(|n| (0..n).map(|x| x as f32 / 3.).collect::<Vec<_>>());
+//! let divergence = fuzz::find_divergence(
+//!     SUM_VERSIONS,
+//!     inputs,
+//!     |f, x| unsafe { f(x) },
+//!     fuzz::within_ulps(4),
+//! );
+//! assert!(divergence.is_none());
+//! ```
+//!
+//! [`multiversion`]: ../attr.multiversion.html
+//! [`find_divergence`]: fn.find_divergence.html
+//! [`assert_agree`]: fn.assert_agree.html
+
+use crate::Version;
+use core::fmt;
+
+/// An input for which two versions of a function produced different outputs.
+#[derive(Clone, Debug)]
+pub struct Divergence<I, R> {
+    /// The input passed to both versions.
+    pub input: I,
+    /// The version that disagreed with the baseline version.
+    pub version: Version,
+    /// The output of the disagreeing version.
+    pub output: R,
+    /// The version every other version is compared to.
+    pub baseline: Version,
+    /// The output of the baseline version.
+    pub expected: R,
+}
+
+impl<I: fmt::Debug, R: fmt::Debug> fmt::Display for Divergence<I, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "version `{}` produced {:?}, but version `{}` produced {:?}, for input {:?}",
+            self.version, self.output, self.baseline, self.expected, self.input
+        )
+    }
+}
+
+// Returns the baseline version: the first supported version without features, which is the
+// default version for the architecture, or otherwise the last supported version.
+fn baseline_version<F>(versions: &[(Version, F)]) -> Option<usize> {
+    let mut supported = versions
+        .iter()
+        .enumerate()
+        .filter(|(_, (version, _))| version.is_supported());
+    supported
+        .clone()
+        .find(|(_, (version, _))| version.features().is_empty())
+        .or_else(|| supported.next_back())
+        .map(|(index, _)| index)
+}
+
+// Returns the first supported version that doesn't agree with the baseline version, along with
+// the baseline version and both outputs.
+fn diverging_version<F: Copy, I: ?Sized, R>(
+    versions: &[(Version, F)],
+    input: &I,
+    call: &mut impl FnMut(F, &I) -> R,
+    agree: &mut impl FnMut(&R, &R) -> bool,
+) -> Option<(Version, R, Version, R)> {
+    let baseline_index = baseline_version(versions)?;
+    let (baseline, baseline_fn) = &versions[baseline_index];
+    let expected = call(*baseline_fn, input);
+    for (index, (version, f)) in versions.iter().enumerate() {
+        if index != baseline_index && version.is_supported() {
+            let output = call(*f, input);
+            if !agree(&output, &expected) {
+                return Some((*version, output, *baseline, expected));
+            }
+        }
+    }
+    None
+}
+
+/// Returns the first input for which a supported version disagrees with the baseline version.
+///
+/// The versions are typically the `{IDENT}_VERSIONS` constant created by [`multiversion`].  The
+/// baseline is the first supported version without features, which is the default version for the
+/// architecture, or otherwise the last supported version.  Unsupported versions are skipped.  For
+/// each input, `call` is called with each supported version's function pointer, so it may safely
+/// call the function pointer.  Outputs are compared with `agree`, such as [`exact`] or
+/// [`within_ulps`].
+///
+/// [`multiversion`]: ../attr.multiversion.html
+/// [`exact`]: fn.exact.html
+/// [`within_ulps`]: fn.within_ulps.html
+pub fn find_divergence<F: Copy, I, R>(
+    versions: &[(Version, F)],
+    inputs: impl IntoIterator<Item = I>,
+    mut call: impl FnMut(F, &I) -> R,
+    mut agree: impl FnMut(&R, &R) -> bool,
+) -> Option<Divergence<I, R>> {
+    for input in inputs {
+        if let Some((version, output, baseline, expected)) =
+            diverging_version(versions, &input, &mut call, &mut agree)
+        {
+            return Some(Divergence {
+                input,
+                version,
+                output,
+                baseline,
+                expected,
+            });
+        }
+    }
+    None
+}
+
+/// Asserts that every supported version agrees with the baseline version for an input.
+///
+/// This is equivalent to [`find_divergence`] with a single input.
+///
+/// # Panics
+/// Panics if any version disagrees, describing the versions, outputs, and input.
+///
+/// [`find_divergence`]: fn.find_divergence.html
+pub fn assert_agree<F: Copy, I: fmt::Debug + ?Sized, R: fmt::Debug>(
+    versions: &[(Version, F)],
+    input: &I,
+    mut call: impl FnMut(F, &I) -> R,
+    mut agree: impl FnMut(&R, &R) -> bool,
+) {
+    if let Some((version, output, baseline, expected)) =
+        diverging_version(versions, input, &mut call, &mut agree)
+    {
+        panic!(
+            "version `{}` produced {:?}, but version `{}` produced {:?}, for input {:?}",
+            version, output, baseline, expected, input
+        );
+    }
+}
+
+/// Compares outputs for equality.
+///
+/// Note that `NaN` is not equal to itself, so floating point outputs should usually be compared
+/// with [`within_ulps`].
+///
+/// [`within_ulps`]: fn.within_ulps.html
+pub fn exact<R: PartialEq + ?Sized>(output: &R, expected: &R) -> bool {
+    output == expected
+}
+
+/// Compares floating point outputs, allowing them to differ by up to `max_ulps` units in the last
+/// place.
+///
+/// See [`UlpsEq`] for details of the comparison.
+///
+/// [`UlpsEq`]: trait.UlpsEq.html
+pub fn within_ulps<R: UlpsEq + ?Sized>(max_ulps: u32) -> impl Fn(&R, &R) -> bool {
+    move |output, expected| output.ulps_eq(expected, max_ulps)
+}
+
+/// Comparison of floating point values, allowing for rounding differences.
+///
+/// Values are equal if they differ by at most `max_ulps` representable values, or units in the last
+/// place.  Values with different signs are only equal if both are zero.  `NaN`s are equal to each
+/// other, regardless of payload, but not to any other value.  Collections are equal if they have
+/// the same length and every element is equal.
+pub trait UlpsEq {
+    /// Returns true if the values are equal within `max_ulps` units in the last place.
+    fn ulps_eq(&self, other: &Self, max_ulps: u32) -> bool;
+}
+
+macro_rules! impl_ulps_eq_float {
+    { $($float:ty),* } => {
+        $(
+        impl UlpsEq for $float {
+            fn ulps_eq(&self, other: &Self, max_ulps: u32) -> bool {
+                if self == other || (self.is_nan() && other.is_nan()) {
+                    true
+                } else if self.is_nan()
+                    || other.is_nan()
+                    || self.is_sign_negative() != other.is_sign_negative()
+                {
+                    false
+                } else {
+                    // Floats with the same sign are ordered by their bit patterns
+                    let (a, b) = (self.to_bits(), other.to_bits());
+                    a.max(b) - a.min(b) <= max_ulps.into()
+                }
+            }
+        }
+        )*
+    }
+}
+
+impl_ulps_eq_float! { f32, f64 }
+
+impl<T: UlpsEq> UlpsEq for [T] {
+    fn ulps_eq(&self, other: &Self, max_ulps: u32) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other.iter())
+                .all(|(a, b)| a.ulps_eq(b, max_ulps))
+    }
+}
+
+impl<T: UlpsEq, const N: usize> UlpsEq for [T; N] {
+    fn ulps_eq(&self, other: &Self, max_ulps: u32) -> bool {
+        self[..].ulps_eq(&other[..], max_ulps)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: UlpsEq> UlpsEq for Vec<T> {
+    fn ulps_eq(&self, other: &Self, max_ulps: u32) -> bool {
+        self[..].ulps_eq(&other[..], max_ulps)
+    }
+}
+
+impl<T: UlpsEq + ?Sized> UlpsEq for &T {
+    fn ulps_eq(&self, other: &Self, max_ulps: u32) -> bool {
+        (**self).ulps_eq(*other, max_ulps)
+    }
+}
+
+impl<T: UlpsEq> UlpsEq for Option<T> {
+    fn ulps_eq(&self, other: &Self, max_ulps: u32) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.ulps_eq(b, max_ulps),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<A: UlpsEq, B: UlpsEq> UlpsEq for (A, B) {
+    fn ulps_eq(&self, other: &Self, max_ulps: u32) -> bool {
+        self.0.ulps_eq(&other.0, max_ulps) && self.1.ulps_eq(&other.1, max_ulps)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ulps_eq() {
+        assert!(1f32.ulps_eq(&1f32, 0));
+        assert!(1f32.ulps_eq(&f32::from_bits(1f32.to_bits() + 2), 2));
+        assert!(!1f32.ulps_eq(&f32::from_bits(1f32.to_bits() + 3), 2));
+        assert!((-1f64).ulps_eq(&f64::from_bits((-1f64).to_bits() - 1), 1));
+        assert!(0f32.ulps_eq(&-0f32, 0));
+        assert!(!f32::MIN_POSITIVE.ulps_eq(&-f32::MIN_POSITIVE, u32::MAX));
+        assert!(f64::NAN.ulps_eq(&-f64::NAN, 0));
+        assert!(!f64::NAN.ulps_eq(&1., u32::MAX));
+        assert!([1f32, 2.].ulps_eq(&[1., 2.], 0));
+        assert!(![1f32, 2.][..].ulps_eq(&[1.][..], 0));
+    }
+}
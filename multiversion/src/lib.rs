@@ -13,18 +13,39 @@
 //! with various features enabled and safely detecting which version to use at runtime.
 //!
 //! # Cargo features
-//! There is one cargo feature, `std`, enabled by default.  When enabled, [`multiversion`] will
+//! The `std` cargo feature is enabled by default.  When enabled, [`multiversion`] will
 //! use CPU feature detection at runtime to dispatch the appropriate function. Disabling this
 //! feature will only allow compile-time function dispatch using `#[cfg(target_feature)]` and can
 //! be used in `#[no_std]` crates.
 //!
+//! The `env-override` cargo feature (which implies `std`) allows limiting the CPU features used by
+//! every dispatcher in the process with environment variables, which is useful for testing and
+//! reproducing bugs as if running on another CPU.  The variables are read once, before the first
+//! function is dispatched:
+//! * `MULTIVERSION_DISABLE_FEATURES`: a comma-separated list of features, such as `avx2,fma`.
+//!   Versions that use any of these features (including implicitly, such as `avx2` when `avx` is
+//!   disabled) are not dispatched.
+//! * `MULTIVERSION_MAX_TARGET`: a target specification string, such as `x86_64-v2` or
+//!   `x86_64+sse4.2`.  Only versions that use a subset of the target's features (including
+//!   implied features) are dispatched.  Versions with features are never dispatched if the target
+//!   is for another architecture, or is `default`.
+//!
+//! Since indirect functions are resolved before the variables can be read, the `ifunc` argument
+//! of [`multiversion`] has no effect with the `env-override` feature.
+//!
+//! Features can also be disabled from code with the [`runtime`] module.
+//!
+//! With the `criterion` cargo feature, the [`bench`](bench/index.html) module benchmarks every
+//! version of a function with [criterion](https://docs.rs/criterion).
+//!
 //! # Capabilities
-//! The intention of this crate is to allow any function, other than trait methods, to be
-//! multiversioned.  If any functions do not work please file an issue on GitHub.
+//! The intention of this crate is to allow any function to be multiversioned.  If any functions
+//! do not work please file an issue on GitHub.
 //!
 //! The [`multiversion`] macro produces additional functions adjacent to the tagged function which
-//! do not correspond to a trait member.  If you would like to multiversion a trait method, instead
-//! try multiversioning a free function or struct method and calling it from the trait method.
+//! do not correspond to a trait member.  To multiversion trait methods, apply the [`multiversion`]
+//! attribute to the trait implementation instead of the individual methods, which places the
+//! additional functions in a separate, hidden trait.
 //!
 //! # Target specification strings
 //! Targets for the [`target`] and [`multiversion`] attributes are specified as a combination of
@@ -42,11 +63,39 @@
 //! The following are all valid target specification strings:
 //! * `"x86"` (matches the `"x86"` architecture)
 //! * `"x86_64+avx+avx2"` (matches the `"x86_64"` architecture with the `"avx"` and `"avx2"`
-//! features)
+//!   features)
 //! * `"[mips|mips64|powerpc|powerpc64]"` (matches any of the `"mips"`, `"mips64"`, `"powerpc"` or
-//! `"powerpc64"` architectures)
+//!   `"powerpc64"` architectures)
 //! * `"[arm|aarch64]+neon"` (matches either the `"arm"` or `"aarch64"` architectures with the
-//! `"neon"` feature)
+//!   `"neon"` feature)
+//!
+//! The x86-64 microarchitecture levels defined by the x86-64 psABI may be used in place of an
+//! architecture, and expand to the `"x86_64"` architecture with the level's features:
+//! * `"x86_64-v2"`: `cmpxchg16b`, `popcnt`, `sse3`, `sse4.1`, `sse4.2`, and `ssse3`
+//! * `"x86_64-v3"`: the features of `"x86_64-v2"`, as well as `avx`, `avx2`, `bmi1`, `bmi2`,
+//!   `f16c`, `fma`, `lzcnt`, `movbe`, and `xsave`
+//! * `"x86_64-v4"`: the features of `"x86_64-v3"`, as well as `avx512f`, `avx512bw`,
+//!   `avx512cd`, `avx512dq`, and `avx512vl`
+//!
+//! The levels may also be spelled as `"x86-64-v2"`, etc. and may be followed by additional features,
+//! such as `"x86_64-v3+aes"`.
+//!
+//! Features imply any features they depend on, for each architecture in the target.  For example,
+//! `"x86_64+avx2"` is equivalent to `"x86_64+avx+avx2"`, since `avx2` implies `avx`.
+//!
+//! Features are checked against a list of known features for the x86, x86-64, ARM, AArch64,
+//! PowerPC, MIPS, RISC-V, LoongArch, s390x, and WebAssembly architectures, and unknown features
+//! are reported as errors.
+//! Features that are not yet known to multiversion can be specified with the `unchecked:` prefix,
+//! such as `"x86_64+avx2+unchecked:avx10.1"`.
+//! Some known features can't be detected at runtime, such as `lor` and `vh` on AArch64, so they
+//! are errors in the targets of [`multiversion`] and [`dispatch_region`], but may be used with
+//! [`target`] and `#[target_cfg]`.
+//!
+//! Features can only be used with a version of Rust that supports them.  For example,
+//! `"x86_64-v2"` requires Rust 1.69, `"x86_64-v3"` requires Rust 1.70, and `"x86_64-v4"` and the
+//! other AVX-512 features require Rust 1.89.  With the `std` feature, runtime detection on RISC-V,
+//! LoongArch, and s390x requires Rust 1.78, 1.89, and 1.93, respectively.
 //!
 //! # Example
 //! The following example is a good candidate for optimization with SIMD.  The function `square`
@@ -141,11 +190,22 @@
 //! * `dispatch!(foo(a, b))`
 //! * `dispatch!(self.foo::<A, B>(a, b))`
 //!
-//! The statically dispatched function must be multiversioned over a subset of CPU features
-//! supported by the caller function.  For example, a function compiled for `x86_64+avx+avx2`
-//! cannot statically dispatch a function compiled for `x86_64+avx`, but a function compiled
-//! for `x86_64+avx` may statically dispatch a multiversioned function compiled for both
-//! `[x86|x86_64]+avx` and `x86+sse` since an exact feature match exists for that architecture.
+//! Function and method calls select the best version of the statically dispatched function that is
+//! supported by the caller: the first version (in the order the clones and specializations are
+//! listed) whose features are a subset of the caller's features, otherwise the default version.
+//! For example, a function compiled for `x86_64+avx+avx2` that calls a function multiversioned
+//! over `[x86|x86_64]+avx` and `x86_64+sse4.1` statically dispatches the `avx` version, and a
+//! function compiled for `x86_64+sse4.2` statically dispatches the `sse4.1` version.  Implied
+//! features are taken into account, so a caller compiled for `x86_64-v3` may also use the `avx`
+//! version.  The version is selected at compile time, so no feature detection occurs.
+//!
+//! Paths (such as `dispatch!(foo)`) refer to a single function, so the statically dispatched
+//! function must have a version with exactly the same features as the caller.  For example, a
+//! function compiled for `x86_64+avx` may take the path of a multiversioned function compiled for
+//! both `[x86|x86_64]+avx` and `x86+sse` since an exact feature match exists for that architecture.
+//!
+//! Static dispatching is also available in blocks of code with the [`dispatch_region`] macro,
+//! which detects features once and then statically dispatches any number of functions.
 //!
 //! # Conditional compilation
 //! The `#[cfg]` attribute allows conditional compilation based on the target architecture and
@@ -156,7 +216,9 @@
 //! The `#[target_cfg]` attribute supports `all`, `any`, and `not` (just like `#[cfg]`) and
 //! supports the following keys:
 //! * `target`: takes a target specification string as a value and is true if the target matches
-//! the function's target
+//!   the function's target
+//! * `cpu`: takes a CPU model name as a value and is true if the function's target was specified
+//!   with that CPU model
 //!
 //! ```
 //! #[multiversion::multiversion]
@@ -176,24 +238,73 @@
 //!
 //! [`target`]: attr.target.html
 //! [`multiversion`]: attr.multiversion.html
+//! [`dispatch_region`]: macro.dispatch_region.html
+//! [`runtime`]: runtime/index.html
+//! [`runtime::set_feature_detector`]: runtime/fn.set_feature_detector.html
 //! [`target_arch`]: https://doc.rust-lang.org/reference/conditional-compilation.html#target_arch
 //! [`target_feature`]: https://doc.rust-lang.org/reference/conditional-compilation.html#target_feature
 
+#[cfg(feature = "criterion")]
+pub mod bench;
+#[cfg(feature = "std")]
+mod cpu_features;
+#[cfg(feature = "std")]
+mod detect;
+pub mod fuzz;
+pub mod runtime;
+mod static_dispatch;
+#[cfg(feature = "std")]
+pub mod testing;
+mod version;
+
+#[cfg(feature = "std")]
+pub use cpu_features::CpuFeatures;
+#[cfg(feature = "std")]
+pub use detect::Detector;
+pub use version::Version;
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+    pub use crate::detect::cpuid_features_detected;
+    #[cfg(feature = "std")]
+    pub use crate::detect::detected_by_detector;
+    pub use crate::runtime::dispatch_allowed;
+    #[cfg(feature = "std")]
+    pub use crate::runtime::testing_allows;
+    #[cfg(feature = "std")]
+    pub use crate::runtime::Dispatched;
+    pub use crate::static_dispatch::{supports, TargetFeatures};
+}
+
 /// Provides function multiversioning.
 ///
-/// Functions are selected in order, calling the first matching target.  The function tagged by the
-/// attribute is the generic implementation that does not require any specific architecture or
-/// features.
+/// Functions are selected in order, calling the first matching target.  Targets with more features
+/// must be specified first, such as `avx2` before `sse4.1`: a target is an error if an earlier
+/// target for the same architecture has a subset of its features, or if, for every architecture it
+/// supports, an earlier target has the same features, since it would never be selected.  The
+/// function tagged by the attribute is the generic implementation that does not require any
+/// specific architecture or features.
+///
+/// # Arguments
+/// * `targets` (optional): a target set created with [`target_set`].  The targets in the set are
+///   cloned before any targets specified with helper attributes.
+/// * `ifunc` (optional): dispatches the function with a GNU indirect function on x86 and x86-64
+///   Linux targets with the GNU C library, and with the atomic function pointer elsewhere.  See
+///   [implementation details] for more information.
 ///
 /// # Helper attributes
 /// * `#[clone]`
 ///   * Clones the function for the specified target.
 ///   * Arguments:
 ///     * `target`: the target specification of the clone
+///     * `cpu` (optional): a CPU model (such as `"skylake"` or `"neoverse-n1"`), adding the features
+///       supported by that CPU to the target.
 /// * `#[specialize]`
 ///   * Specializes the function for the specified target with another function.
 ///   * Arguments:
 ///     * `target`: the target specification of the specialization
+///     * `cpu` (optional): a CPU model, adding the features supported by that CPU to the target.
 ///     * `fn`: path to the function specializing the tagged function
 ///     * `unsafe` (optional): indicates whether the specialization function is `unsafe`, but safe to
 ///       call for this target.
@@ -283,6 +394,136 @@
 /// # fn main() {}
 /// ```
 ///
+/// ## CPU models
+/// Instead of listing features, a target may name a CPU model with the `cpu` argument.  The target
+/// includes every feature supported by that CPU.  The CPU models are named the same as the
+/// `-C target-cpu` option to rustc, for example:
+/// * x86 and x86-64: `"nehalem"`, `"haswell"`, `"skylake"`, `"skylake-avx512"`, `"icelake-server"`,
+///   `"znver3"`, and others
+/// * AArch64: `"cortex-a72"`, `"neoverse-n1"`, `"neoverse-v1"`, and others
+///
+/// Rust does not currently support tuning individual functions for a particular CPU, so only the
+/// CPU's features are applied to the clone.
+/// ```
+/// #[multiversion::multiversion]
+/// #[clone(target = "x86_64", cpu = "znver3")]
+/// #[clone(target = "x86_64", cpu = "haswell")]
+/// #[clone(target = "aarch64", cpu = "neoverse-n1")]
+/// fn square(x: &mut [f32]) {
+///     for v in x {
+///         *v *= *v
+///     }
+/// }
+/// ```
+///
+/// ## Impl blocks
+/// The attribute may also be applied to an `impl` block, multiversioning every method in the block.
+/// The `#[clone]` and `#[crate_path]` helper attributes on the block apply to each method, and each
+/// method may add its own `#[clone]` and `#[specialize]` helper attributes, which are selected
+/// before the targets of the block.  Methods without any targets are left unchanged.  Methods in the block can statically dispatch each other with
+/// `dispatch!`.
+/// ```
+/// struct Buffer(Vec<f32>);
+///
+/// #[multiversion::multiversion]
+/// #[clone(target = "[x86|x86_64]+avx")]
+/// #[clone(target = "x86+sse")]
+/// impl Buffer {
+///     fn square(&mut self) {
+///         for v in self.0.iter_mut() {
+///             *v *= *v
+///         }
+///     }
+///
+///     fn square_plus_one(&mut self) {
+///         dispatch!(self.square());
+///         for v in self.0.iter_mut() {
+///             *v += 1.0;
+///         }
+///     }
+/// }
+/// ```
+///
+/// ## Trait implementations
+/// Trait implementations are multiversioned the same way as other `impl` blocks.  Since a trait
+/// implementation may only contain members of the trait, the versions of each method are placed in
+/// a hidden trait, which is private to the module and implemented for the type.  To multiversion
+/// only some of the methods, apply the [`multiversion`] attribute to the block and the `#[clone]`
+/// helper attributes to those methods.  An attribute applied to a single method can't tell whether
+/// the method is in a trait implementation, so applying it only to a method of a trait
+/// implementation fails to compile, with errors that the versions of the method are not members of
+/// the trait.
+///
+/// If two multiversioned trait implementations for the same type contain methods with the same
+/// name (such as `Mul<f32>` and `Mul<Vector>`), those methods can't be called with `dispatch!`.
+/// ```
+/// #[derive(Copy, Clone)]
+/// struct Vector([f32; 4]);
+///
+/// #[multiversion::multiversion]
+/// impl std::ops::Add for Vector {
+///     type Output = Self;
+///
+///     #[clone(target = "[x86|x86_64]+avx")]
+///     #[clone(target = "x86+sse")]
+///     fn add(mut self, other: Self) -> Self::Output {
+///         for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+///             *a += b;
+///         }
+///         self
+///     }
+/// }
+/// ```
+///
+/// # Versions
+/// The attribute also creates a function `{ident}_versions`, which returns a [`Version`] describing
+/// each version of the function, and a function `{ident}_selected_version`, which returns the
+/// version selected by the dispatcher, reading the selection the dispatcher cached if it has made
+/// one.  This is useful for logging or recording which target is used.  Every supported version
+/// can be tested with the [`testing`] module.  These functions, and the `{IDENT}_VERSIONS`
+/// constant below, have the visibility of the function but are hidden from its crate's
+/// documentation.
+/// ```
+/// #[multiversion::multiversion]
+/// #[clone(target = "[x86|x86_64]+avx")]
+/// #[clone(target = "x86+sse")]
+/// fn square(x: &mut [f32]) {
+///     for v in x {
+///         *v *= *v
+///     }
+/// }
+///
+/// let version = square_selected_version();
+/// println!("square uses target {} (index {})", version.target(), version.index());
+/// ```
+///
+/// Functions that aren't generic, async, associated, or taking or returning `impl Trait` also get a
+/// constant `{IDENT}_VERSIONS`, which pairs each version available on the current architecture with
+/// an `unsafe` function pointer to it.  This allows benchmarks and fuzzers to call every version
+/// without relying on mangled names.  Calling a version is only safe if it is supported by the CPU,
+/// so benchmarks should skip versions that aren't supported, as below.  The [`fuzz`] module
+/// compares the outputs of every version, and the `bench` module benchmarks every version with
+/// the `criterion` cargo feature.
+/// ```
+/// # #[multiversion::multiversion]
+/// # #[clone(target = "[x86|x86_64]+avx")]
+/// # #[clone(target = "x86+sse")]
+/// # fn square(x: &mut [f32]) {
+/// #     for v in x {
+/// #         *v *= *v
+/// #     }
+/// # }
+/// for (version, f) in SQUARE_VERSIONS {
+///     if version.is_supported() {
+///         let mut x = [2.];
+///         unsafe { f(&mut x) };
+///         assert_eq!(x, [4.], "{} failed", version);
+///     }
+/// }
+/// ```
+///
+/// [`fuzz`]: fuzz/index.html
+///
 /// # Static dispatching
 /// The [`multiversion`] attribute allows functions called inside the function to be statically dispatched.
 /// Additionally, functions created with this attribute can themselves be statically dispatched.
@@ -295,18 +536,27 @@
 /// # Function name mangling
 /// The functions created by this macro are mangled as `{ident}_{features}_version`, where `ident` is
 /// the name of the multiversioned function, and `features` is either `default` (for the default
-/// version with no features enabled) or the list of features, sorted alphabetically.  Dots (`.`)
-/// in the feature names are removed.
-///
-/// The following creates two functions, `foo_avx_sse41_version` and `foo_default_version`.
+/// version with no features enabled) or the list of features, sorted alphabetically.  Features
+/// implied by other features in the list (such as `sse4.1`, which is implied by `avx`) are omitted,
+/// and dots (`.`) in the feature names are removed.  Targets that enable all of the features of an
+/// x86-64 microarchitecture level are mangled with the level name followed by any additional
+/// features, such as `foo_x86_64_v3_aes_version` for `"x86_64-v3+aes"`.  Similarly, targets
+/// specified with a CPU model are mangled with the CPU name (with dashes replaced by underscores),
+/// such as `foo_neoverse_n1_version`.
+///
+/// Mangled names may change between releases that make breaking changes, such as when new implied
+/// features are recognized.  The [`Version`] descriptors and function pointers of
+/// `{IDENT}_VERSIONS` don't depend on mangled names, and should be preferred where possible.
+///
+/// The following creates two functions, `foo_avx_xsave_version` and `foo_default_version`.
 /// ```
 /// #[multiversion::multiversion]
-/// #[clone(target = "[x86|x86_64]+sse4.1+avx")]
+/// #[clone(target = "[x86|x86_64]+sse4.1+avx+xsave")]
 /// fn foo() {}
 ///
-/// #[multiversion::target("[x86|x86_64]+sse4.1+avx")]
+/// #[multiversion::target("[x86|x86_64]+sse4.1+avx+xsave")]
 /// unsafe fn call_foo_avx() {
-///     foo_avx_sse41_version();
+///     foo_avx_xsave_version();
 /// }
 ///
 /// fn call_foo_default() {
@@ -323,15 +573,52 @@
 ///
 /// Some comments on the benefits of this implementation:
 /// * The function selector is only invoked once. Subsequent calls are reduced to an atomic load
-/// and indirect function call (for non-generic, non-`async` functions). Generic and `async` functions
-/// cannot be stored in the atomic function pointer, which may result in additional branches.
+///   and indirect function call (for non-generic, non-`async` functions). Generic and `async`
+///   functions, and associated functions in generic `impl` blocks or with their own
+///   `#[multiversion]` attribute, cannot be stored in the atomic function pointer, so they instead
+///   cache the index of the selected version, shared by every instantiation of the function.
+///   Subsequent calls are reduced to an atomic load and a branch to the selected version.
+/// * CPU features are detected once and cached in [`CpuFeatures`], so checking the features of each
+///   version is a single bitmask test.  Features that aren't cached, such as features specified
+///   with `unchecked:`, are detected separately.  If a [`Detector`] is installed, it's used
+///   instead of detecting the CPU's features.
 /// * If called in multiple threads, there is no contention. It is possible for two threads to hit
-/// the same function before function selection has completed, which results in each thread
-/// invoking the function selector, but the atomic ensures that these are synchronized correctly.
+///   the same function before function selection has completed, which results in each thread
+///   invoking the function selector, but the atomic ensures that these are synchronized correctly.
+///
+/// If every feature of a version is enabled during compilation, such as with `-C target-cpu`, and
+/// no earlier version targets the same architecture, the dispatcher calls that version directly,
+/// without any feature detection, so the call may be inlined.  Versions with features after it can
+/// never be selected, so the [`Version`] of each is reported as unsupported.  These versions and
+/// the default version are still compiled and listed with the other versions, so they can be called
+/// by name, and the default version can be tested.  Since the selection is made during
+/// compilation, features disabled with the [`runtime`] module or the `env-override` cargo feature
+/// don't affect it, but the [`testing`] module can still select the default version.
+///
+/// The `ifunc` argument instead selects the version with a GNU indirect function (ifunc), which
+/// the dynamic loader resolves once when the program is loaded, so calls have no atomic load.
+/// This is only used on x86 and x86-64 Linux targets with the GNU C library, and other targets
+/// use the atomic function pointer.  Indirect functions have some limitations:
+/// * The function must not be generic, `async`, associated, or take or return `impl Trait`, and
+///   must be defined in a module rather than a function body.
+/// * The version is selected before `main` with the `cpuid` instruction, so features can't be
+///   disabled with the [`runtime`] or [`testing`] modules.
+/// * Installed detectors are ignored and [`reset_dispatchers`] has no effect, but
+///   `{ident}_selected_version` returns the version selected by the resolver.
+/// * With the `env-override` cargo feature, the atomic function pointer is used instead, so the
+///   environment variables apply to every function.
 ///
 /// [`target`]: attr.target.html
 /// [`multiversion`]: attr.multiversion.html
+/// [`target_set`]: macro.target_set.html
+/// [`Version`]: struct.Version.html
+/// [`testing`]: testing/index.html
+/// [`reset_dispatchers`]: runtime/fn.reset_dispatchers.html
+/// [`runtime`]: runtime/index.html
+/// [`CpuFeatures`]: struct.CpuFeatures.html
+/// [`Detector`]: trait.Detector.html
 /// [static dispatching]: index.html#static-dispatching
+/// [implementation details]: #implementation-details
 /// [conditional compilation]: index.html#conditional-compilation
 pub use multiversion_macros::multiversion;
 
@@ -350,6 +637,10 @@ pub use multiversion_macros::multiversion;
 /// * `#[safe_inner]`
 ///   * Indicates that the inner contents of the function are safe and requires the use of `unsafe`
 ///     blocks to call `unsafe` functions.
+/// * `#[crate_path]`
+///   * Specifies the location of the multiversion crate (useful for re-exporting).
+///   * Arguments:
+///     * `path`: the path to the multiversion crate
 ///
 /// # Static dispatching
 /// The [`target`] attribute allows functions called inside the function to be statically dispatched.
@@ -365,13 +656,121 @@ pub use multiversion_macros::multiversion;
 /// [conditional compilation]: index.html#conditional-compilation
 pub use multiversion_macros::target;
 
+/// Creates a named set of targets.
+///
+/// Lists of `#[clone]` targets are often repeated across many functions.  Instead, a set of
+/// targets can be declared once and used by the [`multiversion`] attribute with the `targets`
+/// argument.  The targets are target specification strings and are checked where the set is
+/// created.
+///
+/// The set is named like a macro, and may be given a visibility up to `pub(crate)`.  Sets in other
+/// modules are referred to by path, for example `#[multiversion(targets = crate::simd::SIMD)]`.
+///
+/// Sets are limited to the crate that creates them.  A set is implemented as a crate-local
+/// `macro_rules!` macro, so it can't be exported or used by other crates, and each crate that
+/// multiversions functions for the same targets must create its own set.
+///
+/// ```
+/// use multiversion::{multiversion, target_set};
+///
+/// target_set!(SIMD = ["x86_64-v3", "x86_64+sse4.1", "aarch64+neon"]);
+///
+/// #[multiversion(targets = SIMD)]
+/// fn square(x: &mut [f32]) {
+///     for v in x {
+///         *v *= *v
+///     }
+/// }
+///
+/// #[multiversion(targets = SIMD)]
+/// #[clone(target = "x86+sse")]
+/// fn square_plus_one(x: &mut [f32]) {
+///     dispatch!(square(x));
+///     for v in x {
+///         *v += 1.0;
+///     }
+/// }
+/// ```
+///
+/// [`multiversion`]: attr.multiversion.html
+pub use multiversion_macros::target_set;
+
+/// Runs a block of code with the best supported set of CPU features.
+///
+/// The block is cloned for each target, like a function tagged with [`multiversion`], and the
+/// clone for the first supported target is run, with targets checked in the same way.  The
+/// selected target is cached like the dispatcher of a function, and feature detection covers the
+/// entire block, so any number of multiversioned functions can be called with
+/// [static dispatching] from within the block, which avoids detecting features in each function
+/// and allows inlining.
+///
+/// The block is run as the body of a closure, so it may capture variables, and `return` (or `?`)
+/// exits the block rather than the enclosing function.  The closure is always inlined into a
+/// function that enables the target's features, so the block is compiled with those features.
+///
+/// # Helper attributes
+/// * `#[clone]`
+///   * Clones the block for the specified target.
+///   * Arguments:
+///     * `target`: the target specification of the clone
+///     * `cpu` (optional): a CPU model, adding the features supported by that CPU to the target.
+/// * `#[crate_path]`
+///   * Specifies the location of the multiversion crate (useful for re-exporting).
+///   * Arguments:
+///     * `path`: the path to the multiversion crate
+///
+/// The block also supports the `#[target_cfg]` helper attribute.  See [conditional compilation]
+/// for more information.
+///
+/// # Example
+/// ```
+/// use multiversion::{dispatch_region, multiversion};
+///
+/// #[multiversion]
+/// #[clone(target = "[x86|x86_64]+avx")]
+/// #[clone(target = "x86+sse")]
+/// fn square(x: &mut [f32]) {
+///     for v in x {
+///         *v *= *v
+///     }
+/// }
+///
+/// #[multiversion]
+/// #[clone(target = "[x86|x86_64]+avx")]
+/// #[clone(target = "x86+sse")]
+/// fn add_one(x: &mut [f32]) {
+///     for v in x {
+///         *v += 1.0
+///     }
+/// }
+///
+/// let mut x = vec![1.0, 2.0, 3.0];
+/// let sum: f32 = dispatch_region! {
+///     #[clone(target = "[x86|x86_64]+avx")]
+///     #[clone(target = "x86+sse")]
+///     {
+///         dispatch!(square(&mut x));
+///         dispatch!(add_one(&mut x));
+///         x.iter().sum()
+///     }
+/// };
+/// assert_eq!(sum, 17.0);
+/// ```
+///
+/// [`multiversion`]: attr.multiversion.html
+/// [static dispatching]: index.html#static-dispatching
+/// [conditional compilation]: index.html#conditional-compilation
+pub use multiversion_macros::dispatch_region;
+
 /// Detects CPU features.
 ///
 /// When the `std` feature is enabled, this macro operates like the standard library detection
 /// macro for the current target (e.g. [`is_x86_feature_detected`]), but accepts multiple arguments.
+/// On architectures without runtime detection in the standard library, such as WebAssembly, this
+/// macro detects if the feature is enabled during compilation.
 ///
-/// When the `std` feature is not enabled, this macro detects if the feature is
-/// enabled during compilation, using the [`cfg`] attribute.
+/// When the `std` feature is not enabled, this macro detects if the feature is enabled during
+/// compilation, using the [`cfg`] attribute.
 ///
 /// [`is_x86_feature_detected`]: https://doc.rust-lang.org/std/macro.is_x86_feature_detected.html
 /// [`cfg`]: https://doc.rust-lang.org/reference/conditional-compilation.html#target_feature
@@ -383,17 +782,23 @@ macro_rules! are_cpu_features_detected {
             #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
             { is_x86_feature_detected!($feature) }
             #[cfg(target_arch = "arm")]
-            { is_arm_feature_detected!($feature) }
+            { ::std::arch::is_arm_feature_detected!($feature) }
             #[cfg(target_arch = "aarch64")]
-            { is_aarch64_feature_detected!($feature) }
+            { ::std::arch::is_aarch64_feature_detected!($feature) }
             #[cfg(target_arch = "powerpc")]
-            { is_powerpc_feature_detected!($feature) }
+            { ::std::arch::is_powerpc_feature_detected!($feature) }
             #[cfg(target_arch = "powerpc64")]
-            { is_powerpc64_feature_detected!($feature) }
+            { ::std::arch::is_powerpc64_feature_detected!($feature) }
             #[cfg(target_arch = "mips")]
-            { is_mips_feature_detected!($feature) }
+            { ::std::arch::is_mips_feature_detected!($feature) }
             #[cfg(target_arch = "mips64")]
-            { is_mips64_feature_detected!($feature) }
+            { ::std::arch::is_mips64_feature_detected!($feature) }
+            #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+            { ::std::arch::is_riscv_feature_detected!($feature) }
+            #[cfg(target_arch = "loongarch64")]
+            { ::std::arch::is_loongarch_feature_detected!($feature) }
+            #[cfg(target_arch = "s390x")]
+            { ::std::arch::is_s390x_feature_detected!($feature) }
             #[cfg(not(any(
                 target_arch = "x86",
                 target_arch = "x86_64",
@@ -403,8 +808,12 @@ macro_rules! are_cpu_features_detected {
                 target_arch = "powerpc64",
                 target_arch = "mips",
                 target_arch = "mips64",
+                target_arch = "riscv32",
+                target_arch = "riscv64",
+                target_arch = "loongarch64",
+                target_arch = "s390x",
             )))]
-            { compile_error!("Unsupported architecture. Expected x86, x86_64, arm, aarch64, powerpc, powerpc64, mips, or mips64.") }
+            { cfg!(target_feature = $feature) }
         }
     };
     { $first:tt, $($features:tt),+ $(,)? } => {
@@ -0,0 +1,459 @@
+//! Runtime control of dispatching.
+//!
+//! By default, dispatchers select a version using the features detected on the CPU.  The
+//! functions in this module change the features available to dispatchers in the entire process,
+//! which is useful for testing each version of multiversioned functions.  These functions require
+//! the `std` cargo feature.
+//!
+//! Functions dispatched with indirect functions (the `ifunc` argument of `#[multiversion]`) select
+//! a version once, when the program is loaded, so they aren't affected by this module.
+//!
+//! ```
+//! # #[cfg(feature = "std")] {
+//! #[multiversion::multiversion]
+//! #[clone(target = "[x86|x86_64]+avx2")]
+//! #[clone(target = "[x86|x86_64]+avx")]
+//! fn square(x: &mut [f32]) {
+//!     for v in x {
+//!         *v *= *v
+//!     }
+//! }
+//!
+//! multiversion::runtime::disable_features(&["avx2"]);
+//! assert_ne!(square_selected_version().target(), "[x86|x86_64]+avx2");
+//! multiversion::runtime::enable_all_features();
+//! # }
+//! ```
+
+#[cfg(feature = "std")]
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+// Features disabled on individual threads, or CPUs simulated on individual threads, used by the
+// `testing` module.  While any thread has an override, dispatchers don't use or update their
+// cached functions.
+#[cfg(feature = "std")]
+pub(crate) mod local {
+    use core::cell::RefCell;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+    enum Override {
+        // Versions using any of these features aren't dispatched
+        Disabled(Vec<&'static str>),
+        // Only versions using a subset of these features are dispatched
+        Cpu(&'static [&'static str]),
+    }
+
+    thread_local! {
+        static OVERRIDE: RefCell<Option<Override>> = const { RefCell::new(None) };
+    }
+
+    #[inline]
+    pub(crate) fn active() -> bool {
+        ACTIVE.load(Ordering::Acquire) != 0
+    }
+
+    pub(crate) fn allows(features: &[&str]) -> bool {
+        OVERRIDE
+            .try_with(|current| match &*current.borrow() {
+                Some(Override::Disabled(disabled)) => {
+                    !features.iter().any(|feature| disabled.contains(feature))
+                }
+                Some(Override::Cpu(supported)) => {
+                    features.iter().all(|feature| supported.contains(feature))
+                }
+                None => true,
+            })
+            .unwrap_or(true)
+    }
+
+    // Restores the previous override when dropped
+    pub(crate) struct Guard(Option<Override>);
+
+    fn set(value: Override) -> Guard {
+        ACTIVE.fetch_add(1, Ordering::AcqRel);
+        Guard(OVERRIDE.with(|previous| previous.replace(Some(value))))
+    }
+
+    pub(crate) fn disable(features: Vec<&'static str>) -> Guard {
+        set(Override::Disabled(features))
+    }
+
+    pub(crate) fn simulate_cpu(features: &'static [&'static str]) -> Guard {
+        set(Override::Cpu(features))
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            OVERRIDE.with(|current| *current.borrow_mut() = previous);
+            ACTIVE.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+mod mask {
+    use core::sync::atomic::AtomicBool;
+    use std::collections::BTreeSet;
+    use std::sync::RwLock;
+
+    pub(super) static ANY_DISABLED: AtomicBool = AtomicBool::new(false);
+    pub(super) static DISABLED: RwLock<BTreeSet<String>> = RwLock::new(BTreeSet::new());
+
+    pub(super) fn allows(features: &[&str]) -> bool {
+        let disabled = DISABLED.read().unwrap_or_else(|e| e.into_inner());
+        !features.iter().any(|feature| disabled.contains(*feature))
+    }
+}
+
+/// Disables CPU features, in addition to any previously disabled features.
+///
+/// Versions of multiversioned functions that use any of these features (including implicitly,
+/// such as `avx2` when `avx` is disabled) are no longer dispatched, even if the CPU supports them.
+/// This resets all dispatchers with [`reset_dispatchers`].  Indirect functions are unaffected.
+#[cfg(feature = "std")]
+pub fn disable_features(features: &[&str]) {
+    {
+        let mut disabled = mask::DISABLED.write().unwrap_or_else(|e| e.into_inner());
+        disabled.extend(features.iter().map(ToString::to_string));
+        mask::ANY_DISABLED.store(!disabled.is_empty(), Ordering::Relaxed);
+    }
+    reset_dispatchers();
+}
+
+/// Enables all CPU features disabled by [`disable_features`].
+///
+/// Features disabled with the `env-override` cargo feature remain disabled.  This resets all
+/// dispatchers with [`reset_dispatchers`].
+#[cfg(feature = "std")]
+pub fn enable_all_features() {
+    {
+        let mut disabled = mask::DISABLED.write().unwrap_or_else(|e| e.into_inner());
+        disabled.clear();
+        mask::ANY_DISABLED.store(false, Ordering::Relaxed);
+    }
+    reset_dispatchers();
+}
+
+// Dispatchers that cache the selected function are registered in a global list when they first
+// select a function, so they can be reset.
+#[cfg(feature = "std")]
+static DISPATCHERS: AtomicPtr<Dispatched> = AtomicPtr::new(core::ptr::null_mut());
+
+/// The cached function pointer of a dispatcher.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub struct Dispatched {
+    current: AtomicPtr<()>,
+    resolver: *mut (),
+    registered: AtomicBool,
+    next: AtomicPtr<Dispatched>,
+}
+
+// The resolver is an immutable function pointer
+#[cfg(feature = "std")]
+unsafe impl Sync for Dispatched {}
+
+#[cfg(feature = "std")]
+impl Dispatched {
+    pub const fn new(resolver: *mut ()) -> Self {
+        Self {
+            current: AtomicPtr::new(resolver),
+            resolver,
+            registered: AtomicBool::new(false),
+            next: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    // Dispatchers that cache the index of the selected version, rather than a function pointer,
+    // store the index as a pointer, which is never dereferenced.
+    const UNRESOLVED: usize = usize::MAX;
+
+    pub const fn new_index() -> Self {
+        Self::new_unresolved()
+    }
+
+    // Dispatchers that can't name a resolver function, such as in methods, start unresolved and
+    // resolve the function themselves
+    pub const fn new_unresolved() -> Self {
+        Self::new(Self::UNRESOLVED as *mut ())
+    }
+
+    #[inline(always)]
+    pub fn load_resolved(&self) -> Option<*mut ()> {
+        let function = self.load();
+        if function as usize == Self::UNRESOLVED {
+            None
+        } else {
+            Some(function)
+        }
+    }
+
+    #[inline(always)]
+    pub fn load_index(&self) -> Option<usize> {
+        let index = self.load() as usize;
+        if index == Self::UNRESOLVED {
+            None
+        } else {
+            Some(index)
+        }
+    }
+
+    pub fn store_index(&'static self, index: usize) {
+        self.store(index as *mut ());
+    }
+
+    #[inline(always)]
+    pub fn load(&self) -> *mut () {
+        if local::active() {
+            return self.resolver;
+        }
+        self.current.load(Ordering::Relaxed)
+    }
+
+    pub fn store(&'static self, function: *mut ()) {
+        if local::active() {
+            return;
+        }
+        self.current.store(function, Ordering::Relaxed);
+        if !self.registered.swap(true, Ordering::AcqRel) {
+            let node = self as *const Self as *mut Self;
+            let mut head = DISPATCHERS.load(Ordering::Acquire);
+            loop {
+                self.next.store(head, Ordering::Relaxed);
+                match DISPATCHERS.compare_exchange_weak(
+                    head,
+                    node,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => break,
+                    Err(current) => head = current,
+                }
+            }
+        }
+    }
+}
+
+/// Installs a detector, which replaces the detection of CPU features for every dispatcher.
+///
+/// The installed detector is used instead of detecting the CPU's features, such as when running
+/// in a sandbox or emulator where native detection is unreliable, or in tests simulating another
+/// CPU.  This resets all dispatchers with [`reset_dispatchers`].  Indirect functions detect
+/// features with `cpuid` when the program is loaded, so they ignore the detector.
+///
+/// This function requires the `std` cargo feature.
+///
+/// # Safety
+/// The detector must only detect features supported by the CPU, since dispatchers may call
+/// versions using any detected feature.
+///
+/// ```
+/// fn detect(feature: &str) -> bool {
+///     feature == "sse2"
+/// }
+///
+/// unsafe { multiversion::runtime::set_feature_detector(&detect) };
+/// # multiversion::runtime::clear_feature_detector();
+/// ```
+#[cfg(feature = "std")]
+pub unsafe fn set_feature_detector(detector: &'static dyn crate::Detector) {
+    crate::detect::set_detector(Some(detector));
+    crate::cpu_features::reset();
+    reset_dispatchers();
+}
+
+/// Removes the detector installed with [`set_feature_detector`], so dispatchers detect the CPU's
+/// features again.  This resets all dispatchers with [`reset_dispatchers`].
+///
+/// This function requires the `std` cargo feature.
+#[cfg(feature = "std")]
+pub fn clear_feature_detector() {
+    crate::detect::set_detector(None);
+    crate::cpu_features::reset();
+    reset_dispatchers();
+}
+
+/// Resets every dispatcher, so the next call to each multiversioned function selects a version
+/// again.
+///
+/// Dispatchers that are running concurrently with this function may select a version using the
+/// previously available features.  Indirect functions are resolved once, when the program is
+/// loaded, so they can't be reset.
+#[cfg(feature = "std")]
+pub fn reset_dispatchers() {
+    let mut node = DISPATCHERS.load(Ordering::Acquire);
+    while let Some(dispatched) = unsafe { node.as_ref() } {
+        dispatched
+            .current
+            .store(dispatched.resolver, Ordering::Relaxed);
+        node = dispatched.next.load(Ordering::Acquire);
+    }
+}
+
+#[cfg(feature = "env-override")]
+mod tables {
+    multiversion_macros::__feature_tables!();
+}
+
+#[cfg(feature = "env-override")]
+mod env {
+    use super::tables::{IMPLIED, LEVELS};
+    use std::collections::BTreeSet;
+    use std::env::consts::ARCH;
+    use std::sync::Mutex;
+
+    const DISABLE_FEATURES: &str = "MULTIVERSION_DISABLE_FEATURES";
+    const MAX_TARGET: &str = "MULTIVERSION_MAX_TARGET";
+
+    struct Override {
+        disabled: BTreeSet<String>,
+        max_target: Option<BTreeSet<String>>,
+    }
+
+    // Returns the features, along with every feature they imply on the current architecture.
+    fn implied_features<'a>(features: impl Iterator<Item = &'a str>) -> BTreeSet<String> {
+        let implications = IMPLIED
+            .iter()
+            .find(|(arches, _)| arches.contains(&ARCH))
+            .map_or(&[][..], |(_, implications)| implications);
+        let mut implied = BTreeSet::new();
+        let mut stack = features.collect::<Vec<_>>();
+        while let Some(feature) = stack.pop() {
+            if implied.insert(feature.to_string()) {
+                if let Some((_, features)) = implications.iter().find(|(f, _)| *f == feature) {
+                    stack.extend(features.iter().copied());
+                }
+            }
+        }
+        implied
+    }
+
+    // Returns the features of a target specification string, or no features if the target
+    // doesn't include the current architecture.
+    fn target_features(target: &str) -> BTreeSet<String> {
+        let mut it = target.split('+');
+        let arch_specifier = it.next().unwrap_or_default();
+        let (arches, level_features): (Vec<&str>, &[&str]) = if let Some((arch, _, features)) =
+            LEVELS.iter().find(|(_, name, _)| *name == arch_specifier)
+        {
+            (vec![arch], features)
+        } else if arch_specifier.starts_with('[') && arch_specifier.ends_with(']') {
+            (
+                arch_specifier[1..arch_specifier.len() - 1]
+                    .split('|')
+                    .collect(),
+                &[],
+            )
+        } else {
+            (vec![arch_specifier], &[])
+        };
+        if arches.contains(&ARCH) {
+            implied_features(level_features.iter().copied().chain(it))
+        } else {
+            BTreeSet::new()
+        }
+    }
+
+    impl Override {
+        fn from_env() -> Self {
+            let disabled = std::env::var(DISABLE_FEATURES)
+                .map(|features| {
+                    features
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|feature| !feature.is_empty())
+                        .map(ToString::to_string)
+                        .collect()
+                })
+                .unwrap_or_default();
+            let max_target = std::env::var(MAX_TARGET).ok().map(|target| {
+                let target = target.trim();
+                if target == "default" {
+                    BTreeSet::new()
+                } else {
+                    target_features(target)
+                }
+            });
+            Self {
+                disabled,
+                max_target,
+            }
+        }
+
+        fn allows(&self, features: &[&str]) -> bool {
+            !features
+                .iter()
+                .any(|feature| self.disabled.contains(*feature))
+                && self.max_target.as_ref().map_or(true, |max_target| {
+                    features.iter().all(|feature| max_target.contains(*feature))
+                })
+        }
+    }
+
+    pub(super) fn allows(features: &[&str]) -> bool {
+        static OVERRIDE: Mutex<Option<Override>> = Mutex::new(None);
+        OVERRIDE
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(Override::from_env)
+            .allows(features)
+    }
+
+    #[cfg(all(test, target_arch = "x86_64"))]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn max_target() {
+            let features = target_features("x86_64-v2+aes");
+            assert!(features.contains("sse4.2"));
+            assert!(features.contains("ssse3"));
+            assert!(features.contains("aes"));
+            assert!(!features.contains("avx"));
+
+            let features = target_features("[x86|x86_64]+avx");
+            assert!(features.contains("avx"));
+            assert!(features.contains("sse2"));
+
+            assert!(target_features("aarch64+neon").is_empty());
+        }
+    }
+}
+
+/// Returns true if the [`testing`] module allows dispatching a version with the specified
+/// features.  Only these overrides apply to versions with features enabled during compilation.
+///
+/// [`testing`]: ../testing/index.html
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[inline]
+pub fn testing_allows(features: &[&str]) -> bool {
+    !local::active() || local::allows(features)
+}
+
+/// Returns true if a version with the specified features may be dispatched.
+#[doc(hidden)]
+#[inline]
+pub fn dispatch_allowed(features: &[&str]) -> bool {
+    #[cfg(feature = "std")]
+    {
+        if mask::ANY_DISABLED.load(Ordering::Relaxed) && !mask::allows(features) {
+            return false;
+        }
+        if local::active() && !local::allows(features) {
+            return false;
+        }
+    }
+    #[cfg(feature = "env-override")]
+    {
+        env::allows(features)
+    }
+    #[cfg(not(feature = "env-override"))]
+    {
+        let _ = features;
+        true
+    }
+}
@@ -0,0 +1,83 @@
+//! Support for selecting function versions during static dispatch.
+
+/// The features enabled for a function version or a statically dispatching function.
+pub trait TargetFeatures {
+    const FEATURES: &'static [&'static str];
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn contains_all(features: &[&str], required: &[&str]) -> bool {
+    let mut i = 0;
+    while i < required.len() {
+        let mut j = 0;
+        let mut found = false;
+        while j < features.len() {
+            if str_eq(features[j], required[i]) {
+                found = true;
+                break;
+            }
+            j += 1;
+        }
+        if !found {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+struct Supports<Caller, Version>(Caller, Version);
+
+impl<Caller: TargetFeatures, Version: TargetFeatures> Supports<Caller, Version> {
+    // Evaluated once per monomorphization, so the version selection is a constant
+    const SUPPORTED: bool = contains_all(Caller::FEATURES, Version::FEATURES);
+}
+
+/// Returns true if the caller's features are a superset of the version's features.
+#[inline(always)]
+pub fn supports<Caller: TargetFeatures, Version: TargetFeatures>(_caller: &Caller) -> bool {
+    Supports::<Caller, Version>::SUPPORTED
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Avx2;
+    impl TargetFeatures for Avx2 {
+        const FEATURES: &'static [&'static str] = &["avx", "avx2", "sse4.1", "sse4.2"];
+    }
+
+    struct Avx;
+    impl TargetFeatures for Avx {
+        const FEATURES: &'static [&'static str] = &["avx", "sse4.1", "sse4.2"];
+    }
+
+    struct Default;
+    impl TargetFeatures for Default {
+        const FEATURES: &'static [&'static str] = &[];
+    }
+
+    #[test]
+    fn supports_subset() {
+        assert!(supports::<_, Avx>(&Avx2));
+        assert!(supports::<_, Avx>(&Avx));
+        assert!(supports::<_, Default>(&Avx));
+        assert!(!supports::<_, Avx2>(&Avx));
+        assert!(!supports::<_, Avx>(&Default));
+    }
+}
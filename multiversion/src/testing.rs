@@ -0,0 +1,179 @@
+//! Utilities for testing every version of multiversioned functions.
+//!
+//! Tests usually only run the version selected for the CPU running the tests.  These functions run
+//! a test once for each version supported by the CPU, with dispatchers on the current thread
+//! selecting that version.  Other threads are unaffected, so tests may run in parallel.  Indirect
+//! functions (the `ifunc` argument of `#[multiversion]`) always call the version selected when the
+//! program was loaded, so their other versions should be tested through `{IDENT}_VERSIONS`.
+//!
+//! ```
+//! #[multiversion::multiversion]
+//! #[clone(target = "[x86|x86_64]+avx")]
+//! #[clone(target = "x86+sse")]
+//! fn sum(x: &[f32]) -> f32 {
+//!     x.iter().sum()
+//! }
+//!
+//! multiversion::testing::assert_versions_agree(sum_versions(), || sum(&[1., 2., 3.]));
+//! ```
+//!
+//! Dispatchers can also select versions as if running on another CPU with [`with_cpu`], which is
+//! useful for testing which version is selected on particular CPUs.
+//!
+//! [`with_cpu`]: fn.with_cpu.html
+
+use crate::runtime::local;
+use crate::{CpuFeatures, Version};
+use core::fmt::{self, Debug};
+
+mod tables {
+    multiversion_macros::__cpu_models!();
+}
+
+// Returns the features to disable so the dispatcher selects the version, or `None` if the version
+// can't be selected.
+fn features_to_disable(versions: &[Version], version: &Version) -> Option<Vec<&'static str>> {
+    // Versions with features are selected in order, followed by versions without features
+    let selected_first = versions.iter().filter(|other| {
+        other.index() != version.index()
+            && other.is_supported()
+            && if version.features().is_empty() {
+                !other.features().is_empty() || other.index() < version.index()
+            } else {
+                !other.features().is_empty() && other.index() < version.index()
+            }
+    });
+
+    let mut disabled = Vec::new();
+    for other in selected_first {
+        let feature = other
+            .features()
+            .iter()
+            .find(|feature| !version.features().contains(feature))?;
+        disabled.push(*feature);
+    }
+    Some(disabled)
+}
+
+/// Calls a function once for each version supported by the CPU.
+///
+/// The function is passed the version, and every dispatcher for the function on the current thread
+/// selects that version while the function is called.  Versions that can never be selected, because
+/// a preceding version is always supported when they are, are skipped.
+pub fn for_each_version(versions: &[Version], mut f: impl FnMut(&Version)) {
+    for version in versions.iter().filter(|version| version.is_supported()) {
+        if let Some(disabled) = features_to_disable(versions, version) {
+            let _guard = local::disable(disabled);
+            f(version);
+        }
+    }
+}
+
+/// Asserts that every version supported by the CPU produces the same result as a baseline version.
+///
+/// The function is called once for each version, as with [`for_each_version`], and should call the
+/// multiversioned function.  The baseline is the default version, or the version without features
+/// that replaces it on the current architecture, if it's supported, and otherwise the first
+/// supported version.
+///
+/// # Panics
+/// Panics if any version produces a different result than the baseline, or if no version is
+/// supported, so there is no baseline version.
+///
+/// [`for_each_version`]: fn.for_each_version.html
+pub fn assert_versions_agree<R: PartialEq + Debug>(versions: &[Version], mut f: impl FnMut() -> R) {
+    let mut results = Vec::new();
+    for_each_version(versions, |version| results.push((*version, f())));
+    let (baseline, expected) = results
+        .iter()
+        .find(|(version, _)| version.features().is_empty())
+        .or_else(|| results.first())
+        .expect("no baseline version available");
+    for (version, result) in &results {
+        assert!(
+            result == expected,
+            "version `{}` produced {:?}, but baseline version `{}` produced {:?}",
+            version,
+            result,
+            baseline,
+            expected
+        );
+    }
+}
+
+/// The reason a CPU can't be simulated by [`with_cpu`].
+///
+/// [`with_cpu`]: fn.with_cpu.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnsupportedCpu {
+    /// The CPU model isn't known.
+    Unknown,
+    /// The CPU model is for another architecture.
+    OtherArchitecture,
+    /// The CPU running the program doesn't support some of the CPU model's features.
+    MissingFeatures(Vec<&'static str>),
+}
+
+impl fmt::Display for UnsupportedCpu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unknown => write!(f, "unknown CPU model"),
+            Self::OtherArchitecture => write!(f, "CPU model is for another architecture"),
+            Self::MissingFeatures(features) => write!(
+                f,
+                "CPU doesn't support features of CPU model: {}",
+                features.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UnsupportedCpu {}
+
+/// Calls a function with dispatchers on the current thread selecting versions as if running on a
+/// CPU model.
+///
+/// The CPU models are named the same as the `cpu` argument of [`multiversion`], such as
+/// `"haswell"`, `"nehalem"`, or `"neoverse-n1"` (AWS Graviton2).  Each dispatcher selects the first
+/// version whose features are all supported by the CPU model.  Other threads are unaffected.
+///
+/// Versions are only run if the CPU running the program supports them, so the CPU model must be
+/// for the current architecture, and the CPU must support every feature of the CPU model that can
+/// be detected.  Otherwise, the function isn't called and an error is returned.
+///
+/// ```
+/// #[multiversion::multiversion]
+/// #[clone(target = "[x86|x86_64]+avx2")]
+/// #[clone(target = "[x86|x86_64]+sse4.2")]
+/// fn version() -> &'static str {
+///     version_selected_version().target()
+/// }
+///
+/// if let Ok(selected) = multiversion::testing::with_cpu("nehalem", version) {
+///     assert_eq!(selected, "[x86|x86_64]+sse4.2");
+/// }
+/// ```
+///
+/// [`multiversion`]: ../attr.multiversion.html
+pub fn with_cpu<R>(cpu: &str, f: impl FnOnce() -> R) -> Result<R, UnsupportedCpu> {
+    let (arches, _, features) = tables::CPUS
+        .iter()
+        .find(|(_, name, _)| *name == cpu)
+        .ok_or(UnsupportedCpu::Unknown)?;
+    if !arches.contains(&std::env::consts::ARCH) {
+        return Err(UnsupportedCpu::OtherArchitecture);
+    }
+
+    let detected = CpuFeatures::detected();
+    let missing = features
+        .iter()
+        .copied()
+        .filter(|feature| crate::cpu_features::is_cached(feature) && !detected.contains(feature))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(UnsupportedCpu::MissingFeatures(missing));
+    }
+
+    let _guard = local::simulate_cpu(features);
+    Ok(f())
+}
@@ -0,0 +1,129 @@
+use core::fmt;
+use core::hash::{Hash, Hasher};
+
+/// Describes a version of a multiversioned function.
+///
+/// Every version of a multiversioned function is returned by the function's companion
+/// `{ident}_versions` function, and the version selected by the dispatcher is returned by
+/// `{ident}_selected_version`.
+#[derive(Copy, Clone)]
+pub struct Version {
+    index: usize,
+    target: &'static str,
+    cpu: Option<&'static str>,
+    features: &'static [&'static str],
+    default: bool,
+    supported: fn() -> bool,
+}
+
+impl Version {
+    #[doc(hidden)]
+    pub const fn __new(
+        index: usize,
+        target: &'static str,
+        cpu: Option<&'static str>,
+        features: &'static [&'static str],
+        default: bool,
+        supported: fn() -> bool,
+    ) -> Self {
+        Self {
+            index,
+            target,
+            cpu,
+            features,
+            default,
+            supported,
+        }
+    }
+
+    /// Returns the position of the version in the order versions are selected.
+    ///
+    /// This is the version's position in the function's list of versions, after targets are
+    /// ordered so that supersets of features are selected first.  The default version, which has
+    /// no target, is last.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the target specification string of the version, or `"default"` for the default
+    /// version.
+    pub fn target(&self) -> &'static str {
+        self.target
+    }
+
+    /// Returns the CPU model of the version's target, if the target was specified with one.
+    pub fn cpu(&self) -> Option<&'static str> {
+        self.cpu
+    }
+
+    /// Returns the CPU features enabled by the version, including implied features.
+    pub fn features(&self) -> &'static [&'static str] {
+        self.features
+    }
+
+    /// Returns true if the CPU supports this version.
+    ///
+    /// Only the CPU's features are checked, so this ignores any features disabled with the
+    /// [`runtime`] module.  Versions that can't be selected, because an earlier version's features
+    /// are enabled during compilation, are never supported.
+    ///
+    /// [`runtime`]: runtime/index.html
+    pub fn is_supported(&self) -> bool {
+        (self.supported)()
+    }
+
+    /// Returns true if this is the default version, which is the function without a target.
+    ///
+    /// Targets without features, such as `x86_64`, replace the default version on their
+    /// architectures, but aren't the default version.  Versions without any features can be
+    /// identified with [`features`].
+    ///
+    /// [`features`]: #method.features
+    pub fn is_default(&self) -> bool {
+        self.default
+    }
+}
+
+// The detection function isn't compared, since function pointers may not be unique
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+            && self.target == other.target
+            && self.cpu == other.cpu
+            && self.features == other.features
+            && self.default == other.default
+    }
+}
+
+impl Eq for Version {}
+
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.target.hash(state);
+        self.cpu.hash(state);
+        self.features.hash(state);
+        self.default.hash(state);
+    }
+}
+
+impl fmt::Debug for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Version")
+            .field("index", &self.index)
+            .field("target", &self.target)
+            .field("cpu", &self.cpu)
+            .field("features", &self.features)
+            .finish()
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(cpu) = self.cpu {
+            write!(f, "{} (cpu = {})", self.target, cpu)
+        } else {
+            f.write_str(self.target)
+        }
+    }
+}
@@ -0,0 +1,37 @@
+#![cfg(feature = "criterion")]
+
+use criterion::Criterion;
+use multiversion::{bench::bench_all_versions, multiversion};
+use std::time::Duration;
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "x86_64+sse2")]
+#[clone(target = "aarch64+neon")]
+fn double(x: u32) -> u32 {
+    x * 2
+}
+
+#[test]
+fn supported_versions() {
+    let mut c = Criterion::default()
+        .sample_size(10)
+        .warm_up_time(Duration::from_millis(1))
+        .measurement_time(Duration::from_millis(1))
+        .nresamples(100)
+        .without_plots();
+    let mut benchmarked = Vec::new();
+    bench_all_versions(&mut c, "double", DOUBLE_VERSIONS, &2, |b, f, x| {
+        assert_eq!(unsafe { f(*x) }, 4);
+        benchmarked.push(f as usize);
+        b.iter(|| unsafe { f(*x) })
+    });
+    benchmarked.dedup();
+
+    let expected = DOUBLE_VERSIONS
+        .iter()
+        .filter(|(version, _)| version.is_supported())
+        .map(|(_, f)| *f as usize)
+        .collect::<Vec<_>>();
+    assert_eq!(benchmarked, expected);
+}
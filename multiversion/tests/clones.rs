@@ -34,6 +34,19 @@ unsafe fn priv_unsafe_add(a: &mut [f32], b: &[f32]) {
     a.iter_mut().zip(b.iter()).for_each(|(a, b)| *a += b);
 }
 
+#[multiversion]
+#[clone(target = "aarch64+sve2-aes")]
+#[clone(target = "powerpc64+power8-altivec")]
+fn dashed_feature_add(a: &mut [f32], b: &[f32]) {
+    a.iter_mut().zip(b.iter()).for_each(|(a, b)| *a += b);
+}
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+unchecked:avx2")]
+fn unchecked_add(a: &mut [f32], b: &[f32]) {
+    a.iter_mut().zip(b.iter()).for_each(|(a, b)| *a += b);
+}
+
 struct Adder(f32);
 
 impl Adder {
@@ -93,6 +106,10 @@ mod test {
             priv_unsafe_add(&mut a, &b);
         }
         assert_eq!(a, vec![4f32, 6f32, 8f32]);
+        unchecked_add(&mut a, &b);
+        assert_eq!(a, vec![5f32, 7f32, 9f32]);
+        dashed_feature_add(&mut a, &b);
+        assert_eq!(a, vec![6f32, 8f32, 10f32]);
     }
 
     #[test]
@@ -0,0 +1,66 @@
+// A multiversioned function shared by the tests that change how versions are selected at runtime.
+// Each version returns its index.
+#![allow(dead_code)]
+
+use multiversion::multiversion;
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "[x86|x86_64]+sse4.1")]
+pub fn version() -> usize {
+    #[target_cfg(target = "[x86|x86_64]+avx2")]
+    return 0;
+
+    #[target_cfg(target = "[x86|x86_64]+avx")]
+    return 1;
+
+    #[target_cfg(target = "[x86|x86_64]+sse4.1")]
+    return 2;
+
+    #[target_cfg(not(any(
+        target = "[x86|x86_64]+avx2",
+        target = "[x86|x86_64]+avx",
+        target = "[x86|x86_64]+sse4.1"
+    )))]
+    return 3;
+}
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "[x86|x86_64]+sse4.1")]
+pub fn generic_version<T: Default>() -> (T, usize) {
+    (T::default(), dispatch!(version()))
+}
+
+// Returns the index of the version selected for the CPU's features
+pub fn detected() -> usize {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if multiversion::are_cpu_features_detected!("avx2") {
+            return 0;
+        } else if multiversion::are_cpu_features_detected!("avx") {
+            return 1;
+        } else if multiversion::are_cpu_features_detected!("sse4.1") {
+            return 2;
+        }
+    }
+    3
+}
+
+// Returns the index of the first version with features enabled during compilation, such as with
+// `-C target-cpu`, which is selected regardless of the features available at runtime
+pub fn enabled() -> Option<usize> {
+    if cfg!(not(any(target_arch = "x86", target_arch = "x86_64"))) {
+        None
+    } else if cfg!(target_feature = "avx2") {
+        Some(0)
+    } else if cfg!(target_feature = "avx") {
+        Some(1)
+    } else if cfg!(target_feature = "sse4.1") {
+        Some(2)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,55 @@
+use multiversion::multiversion;
+
+#[multiversion]
+#[clone(target = "x86_64", cpu = "skylake-avx512")]
+#[clone(target = "[x86|x86_64]", cpu = "haswell")]
+#[clone(target = "aarch64", cpu = "neoverse-n1")]
+fn add(a: &mut [f32], b: &[f32]) {
+    a.iter_mut().zip(b.iter()).for_each(|(a, b)| *a += b);
+}
+
+#[multiversion]
+#[clone(target = "x86_64", cpu = "znver3")]
+#[clone(target = "x86_64+avx")]
+fn cpu_name() -> u32 {
+    #[target_cfg(cpu = "znver3")]
+    return 3;
+
+    #[target_cfg(not(cpu = "znver3"))]
+    return 0;
+}
+
+#[test]
+fn cpu() {
+    let mut a = vec![0f32, 2f32, 4f32];
+    add(&mut a, &[1f32, 1f32, 1f32]);
+    assert_eq!(a, vec![1f32, 3f32, 5f32]);
+
+    // The znver3 version is selected if the CPU supports every znver3 feature
+    let znver3 = cfg!(target_arch = "x86_64") && cpu_name_versions()[0].is_supported();
+    assert_eq!(cpu_name(), if znver3 { 3 } else { 0 });
+
+    // CPU clones are mangled with the CPU name
+    #[cfg(target_arch = "x86_64")]
+    {
+        let _ = add_haswell_version;
+        let _ = add_skylake_avx512_version;
+    }
+}
+
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+#[test]
+fn cpu_selection() {
+    use multiversion::testing::with_cpu;
+
+    let selected = || (cpu_name_selected_version().index(), cpu_name());
+    if let Ok(selected) = with_cpu("nehalem", selected) {
+        assert_eq!(selected, (2, 0));
+    }
+    if let Ok(selected) = with_cpu("haswell", selected) {
+        assert_eq!(selected, (1, 0));
+    }
+    if let Ok(selected) = with_cpu("znver3", selected) {
+        assert_eq!(selected, (0, 3));
+    }
+}
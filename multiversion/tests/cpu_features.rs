@@ -0,0 +1,14 @@
+#![cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+
+use multiversion::CpuFeatures;
+
+#[test]
+fn detected() {
+    let features = CpuFeatures::detected();
+    assert_eq!(features, CpuFeatures::detected());
+    assert_eq!(features.contains("sse2"), is_x86_feature_detected!("sse2"));
+    assert_eq!(features.contains("avx"), is_x86_feature_detected!("avx"));
+    assert_eq!(features.contains("avx2"), is_x86_feature_detected!("avx2"));
+    assert!(!features.contains("neon"));
+    assert!(features.iter().all(|feature| features.contains(feature)));
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::*;
+use multiversion::{runtime, CpuFeatures};
+
+fn no_features(_: &str) -> bool {
+    false
+}
+
+// Detects the features of a CPU with SSE4.2 but not AVX, if the host supports them
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+struct Nehalem;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+impl multiversion::Detector for Nehalem {
+    fn is_feature_detected(&self, feature: &str) -> bool {
+        ["sse", "sse2", "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt"].contains(&feature)
+            && multiversion::are_cpu_features_detected!("sse4.1", "sse4.2", "popcnt")
+    }
+}
+
+#[test]
+fn detector() {
+    let native = version();
+
+    unsafe { runtime::set_feature_detector(&no_features) };
+    assert_eq!(CpuFeatures::detected().iter().count(), 0);
+    assert_eq!(version(), enabled().unwrap_or(3));
+    assert_eq!(generic_version::<u8>().1, enabled().unwrap_or(3));
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if multiversion::are_cpu_features_detected!("sse4.1", "sse4.2", "popcnt") {
+        unsafe { runtime::set_feature_detector(&Nehalem) };
+        assert!(CpuFeatures::detected().contains("sse4.1"));
+        assert!(!CpuFeatures::detected().contains("avx"));
+        assert_eq!(version(), enabled().unwrap_or(2));
+        assert_eq!(generic_version::<u8>().1, enabled().unwrap_or(2));
+    }
+
+    runtime::clear_feature_detector();
+    assert_eq!(version(), native);
+}
@@ -0,0 +1,85 @@
+use multiversion::{dispatch_region, multiversion};
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2+fma")]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "x86+sse")]
+fn square(x: &mut [f32]) {
+    for v in x {
+        *v *= *v
+    }
+}
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "x86+sse")]
+fn add_one(x: &mut [f32]) {
+    for v in x {
+        *v += 1.0
+    }
+}
+
+#[test]
+fn dispatch_region() {
+    let mut x = vec![0f32, 1f32, 2f32];
+    let len = dispatch_region! {
+        #[clone(target = "[x86|x86_64]+avx2+fma")]
+        #[clone(target = "[x86|x86_64]+avx")]
+        #[clone(target = "x86+sse")]
+        {
+            dispatch!(square(&mut x));
+            dispatch!(add_one(&mut x));
+            x.len()
+        }
+    };
+    assert_eq!(len, 3);
+    assert_eq!(x, vec![1f32, 2f32, 5f32]);
+}
+
+#[test]
+fn dispatch_region_target_cfg() {
+    let version = dispatch_region! {
+        #[clone(target = "[x86|x86_64]+avx")]
+        #[clone(target = "[arm|aarch64]+neon")]
+        {
+            #[target_cfg(target = "[x86|x86_64]+avx")]
+            return "avx";
+
+            #[target_cfg(target = "[arm|aarch64]+neon")]
+            return "neon";
+
+            #[target_cfg(not(any(target = "[x86|x86_64]+avx", target = "[arm|aarch64]+neon")))]
+            return "default";
+        }
+    };
+    assert!(["avx", "neon", "default"].contains(&version));
+}
+
+#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+#[test]
+fn dispatch_region_disabled_features() {
+    let version = || {
+        dispatch_region! {
+            #[clone(target = "[x86|x86_64]+avx")]
+            {
+                #[target_cfg(target = "[x86|x86_64]+avx")]
+                return "avx";
+
+                #[target_cfg(not(target = "[x86|x86_64]+avx"))]
+                return "default";
+            }
+        }
+    };
+    let detected = if multiversion::are_cpu_features_detected!("avx") {
+        "avx"
+    } else {
+        "default"
+    };
+    assert_eq!(version(), detected);
+
+    // The selected target is cached until the dispatchers are reset
+    multiversion::runtime::disable_features(&["avx"]);
+    assert_eq!(version(), "default");
+    multiversion::runtime::enable_all_features();
+    assert_eq!(version(), detected);
+}
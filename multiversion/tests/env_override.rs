@@ -0,0 +1,17 @@
+#![cfg(feature = "env-override")]
+
+mod common;
+
+use common::*;
+
+#[test]
+fn env_override() {
+    // The overrides are read once, before anything is dispatched.  The AVX2 version is disabled,
+    // and the AVX version exceeds the maximum target.
+    std::env::set_var("MULTIVERSION_DISABLE_FEATURES", "avx2, popcnt");
+    std::env::set_var("MULTIVERSION_MAX_TARGET", "[x86|x86_64]+sse4.2");
+
+    let expected = enabled().unwrap_or_else(|| detected().max(2));
+    assert_eq!(version(), expected);
+    assert_eq!(version_selected_version().index(), expected);
+}
@@ -0,0 +1,83 @@
+use multiversion::{fuzz, multiversion};
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "x86+sse")]
+fn sum(x: &[f32]) -> f32 {
+    x.iter().sum()
+}
+
+#[multiversion]
+#[clone(target = "x86_64+sse2")]
+fn disagree(x: u32) -> u32 {
+    #[target_cfg(target = "x86_64+sse2")]
+    return x + 1;
+
+    #[target_cfg(not(target = "x86_64+sse2"))]
+    return x;
+}
+
+#[test]
+fn agree() {
+    let inputs = (0..100).map(|n| (0..n).map(|x| x as f32 / 3.).collect::<Vec<_>>());
+    let divergence = fuzz::find_divergence(
+        SUM_VERSIONS,
+        inputs,
+        |f, x| unsafe { f(x) },
+        fuzz::within_ulps(4),
+    );
+    assert!(divergence.is_none());
+
+    fuzz::assert_agree(
+        SUM_VERSIONS,
+        &[1f32, 2., 3.][..],
+        |f, x| unsafe { f(x) },
+        fuzz::exact,
+    );
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn divergence() {
+    let divergence = fuzz::find_divergence(
+        DISAGREE_VERSIONS,
+        5..10,
+        |f, x| unsafe { f(*x) },
+        fuzz::exact,
+    )
+    .unwrap();
+    assert_eq!(divergence.input, 5);
+    assert_eq!(divergence.version.target(), "x86_64+sse2");
+    assert_eq!(divergence.output, 6);
+    assert!(divergence.baseline.is_default());
+    assert_eq!(divergence.expected, 5);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+#[should_panic(
+    expected = "version `x86_64+sse2` produced 6, but version `default` produced 5, for input 5"
+)]
+fn assert_disagree() {
+    fuzz::assert_agree(DISAGREE_VERSIONS, &5, |f, x| unsafe { f(*x) }, fuzz::exact);
+}
+
+// The baseline is the default version, even if it isn't last
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn divergence_clone_last() {
+    let reversed = DISAGREE_VERSIONS.iter().rev().copied().collect::<Vec<_>>();
+    let divergence =
+        fuzz::find_divergence(&reversed, 5..10, |f, x| unsafe { f(*x) }, fuzz::exact).unwrap();
+    assert_eq!(divergence.version.target(), "x86_64+sse2");
+    assert!(divergence.baseline.is_default());
+
+    // Without the default version, the clone is its own baseline
+    let divergence = fuzz::find_divergence(
+        &DISAGREE_VERSIONS[..1],
+        5..10,
+        |f, x| unsafe { f(*x) },
+        fuzz::exact,
+    );
+    assert!(divergence.is_none());
+}
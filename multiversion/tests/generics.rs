@@ -54,3 +54,52 @@ mod test {
         assert_eq!(y, vec![2f64, 4f64, 8f64]);
     }
 }
+
+// The target's features are in a different position in each architecture's bitmask, so the
+// dispatcher asks the detector directly each time it selects a version
+#[cfg(all(feature = "std", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod cache {
+    use multiversion::{multiversion, runtime, Detector};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static SELECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counting;
+
+    impl Detector for Counting {
+        fn is_feature_detected(&self, feature: &str) -> bool {
+            if feature == "aes" {
+                SELECTIONS.fetch_add(1, Ordering::Relaxed);
+            }
+            false
+        }
+    }
+
+    #[multiversion]
+    #[clone(target = "[x86_64|aarch64]+aes")]
+    fn default<T: Default>() -> T {
+        T::default()
+    }
+
+    #[test]
+    fn selected_once() {
+        unsafe { runtime::set_feature_detector(&Counting) };
+        SELECTIONS.store(0, Ordering::Relaxed);
+
+        assert_eq!(default::<u8>(), 0);
+        assert_eq!(default::<u8>(), 0);
+        assert_eq!(SELECTIONS.load(Ordering::Relaxed), 1);
+
+        // The selection is shared by every instantiation
+        assert_eq!(default::<f64>(), 0.);
+        assert_eq!(default::<u8>(), 0);
+        assert_eq!(SELECTIONS.load(Ordering::Relaxed), 1);
+        assert!(default_selected_version().is_default());
+
+        runtime::reset_dispatchers();
+        assert_eq!(default::<f64>(), 0.);
+        assert_eq!(SELECTIONS.load(Ordering::Relaxed), 2);
+
+        runtime::clear_feature_detector();
+    }
+}
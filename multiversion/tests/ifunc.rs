@@ -0,0 +1,171 @@
+use multiversion::{multiversion, target_set};
+
+#[multiversion(ifunc)]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "aarch64+neon")]
+fn square(x: &mut [f32]) {
+    for v in x {
+        *v *= *v
+    }
+}
+
+#[multiversion(ifunc)]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "x86_64")]
+fn version(x: &str) -> (&str, usize) {
+    #[target_cfg(target = "[x86|x86_64]+avx")]
+    return (x, 0);
+
+    #[target_cfg(not(target = "[x86|x86_64]+avx"))]
+    return (x, 1);
+}
+
+mod other {
+    // Functions in other modules may have the same name
+    #[multiversion::multiversion(ifunc)]
+    #[clone(target = "[x86|x86_64]+avx")]
+    pub fn version(x: &str) -> (&str, usize) {
+        (x, 2)
+    }
+}
+
+target_set! {
+    AVX = ["[x86|x86_64]+avx"]
+}
+
+#[multiversion(targets = AVX, ifunc)]
+fn target_set_version() -> usize {
+    #[target_cfg(target = "[x86|x86_64]+avx")]
+    return 0;
+
+    #[target_cfg(not(target = "[x86|x86_64]+avx"))]
+    return 1;
+}
+
+#[test]
+fn ifunc() {
+    let mut x = vec![1f32, 2., 3.];
+    square(&mut x);
+    assert_eq!(x, vec![1., 4., 9.]);
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    let avx = multiversion::are_cpu_features_detected!("avx");
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    let avx = false;
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    let sha = multiversion::are_cpu_features_detected!("sha");
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    let sha = false;
+    assert_eq!(indirect(), sha);
+
+    let expected = if avx { 0 } else { 1 };
+    assert_eq!(version("x"), ("x", expected));
+    assert_eq!(version_selected_version().index(), expected);
+    #[cfg(feature = "std")]
+    {
+        // The version is selected once by the resolver
+        multiversion::runtime::reset_dispatchers();
+        assert_eq!(version_selected_version().index(), expected);
+    }
+    assert_eq!(other::version("y"), ("y", 2));
+    assert_eq!(target_set_version(), expected);
+}
+
+// The x86-64 microarchitecture levels don't enable SHA, so this is an indirect function unless
+// compiling for a particular CPU
+#[multiversion(ifunc)]
+#[clone(target = "[x86|x86_64]+sha")]
+fn indirect() -> bool {
+    #[target_cfg(target = "[x86|x86_64]+sha")]
+    return true;
+
+    #[target_cfg(not(target = "[x86|x86_64]+sha"))]
+    return false;
+}
+
+// Returns the type of each symbol in the test executable's symbol table
+#[cfg(all(feature = "std", target_os = "linux", target_pointer_width = "64"))]
+fn symbol_types() -> Vec<(String, u8)> {
+    let elf = std::fs::read("/proc/self/exe").unwrap();
+    let u16_at = |offset: usize| u16::from_le_bytes([elf[offset], elf[offset + 1]]) as usize;
+    let u32_at = |offset: usize| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&elf[offset..offset + 4]);
+        u32::from_le_bytes(bytes) as usize
+    };
+    let u64_at = |offset: usize| {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&elf[offset..offset + 8]);
+        u64::from_le_bytes(bytes) as usize
+    };
+
+    const SHT_SYMTAB: usize = 2;
+    let sections = (0..u16_at(0x3c)).map(|index| u64_at(0x28) + index * u16_at(0x3a));
+    let mut symbols = Vec::new();
+    for symtab in sections
+        .clone()
+        .filter(|header| u32_at(header + 4) == SHT_SYMTAB)
+    {
+        let strtab = sections.clone().nth(u32_at(symtab + 40)).unwrap();
+        let names = u64_at(strtab + 24);
+        for symbol in (u64_at(symtab + 24)..u64_at(symtab + 24) + u64_at(symtab + 32)).step_by(24) {
+            let name = &elf[names + u32_at(symbol)..];
+            let name = &name[..name.iter().position(|byte| *byte == 0).unwrap()];
+            symbols.push((
+                String::from_utf8_lossy(name).into_owned(),
+                elf[symbol + 4] & 0xf,
+            ));
+        }
+    }
+    symbols
+}
+
+#[cfg(all(
+    feature = "std",
+    not(feature = "env-override"),
+    target_os = "linux",
+    target_env = "gnu",
+    target_arch = "x86_64"
+))]
+#[test]
+fn ifunc_symbol() {
+    const STT_GNU_IFUNC: u8 = 10;
+    assert_eq!(indirect(), multiversion::are_cpu_features_detected!("sha"));
+    let types = symbol_types();
+    let symbol_type = |name: &str| {
+        types
+            .iter()
+            .find(|(symbol, _)| symbol == name)
+            .map(|(_, symbol_type)| *symbol_type)
+    };
+    assert_eq!(
+        symbol_type("ifunc::indirect::__multiversion_ifunc"),
+        Some(STT_GNU_IFUNC)
+    );
+    // Functions with features enabled during compilation are called directly
+    let expected = if cfg!(target_feature = "avx") {
+        None
+    } else {
+        Some(STT_GNU_IFUNC)
+    };
+    assert_eq!(
+        symbol_type("ifunc::other::version::__multiversion_ifunc"),
+        expected
+    );
+}
+
+// Indirect functions aren't used with the `env-override` feature, so the environment variables
+// apply to them
+#[cfg(all(
+    feature = "env-override",
+    target_os = "linux",
+    target_pointer_width = "64"
+))]
+#[test]
+fn env_override_disables_ifunc() {
+    assert!(symbol_types()
+        .iter()
+        .all(|(symbol, _)| !symbol.ends_with("__multiversion_ifunc")));
+}
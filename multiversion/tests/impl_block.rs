@@ -0,0 +1,115 @@
+use multiversion::multiversion;
+
+struct Buffer {
+    data: Vec<f32>,
+}
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2+fma")]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "x86+sse")]
+impl Buffer {
+    const SCALE: f32 = 2.0;
+
+    fn new(data: Vec<f32>) -> Self {
+        Self { data }
+    }
+
+    fn scale(&mut self) {
+        for v in self.data.iter_mut() {
+            *v *= Self::SCALE;
+        }
+    }
+
+    fn add(&mut self, x: f32) {
+        for v in self.data.iter_mut() {
+            *v += x;
+        }
+    }
+
+    fn scale_and_add(&mut self, x: f32) {
+        dispatch!(self.scale());
+        dispatch!(Self::add(self, x));
+    }
+
+    fn sum(&self) -> f32 {
+        let sum = dispatch!(Self::sum_inner);
+        sum(self)
+    }
+
+    #[clone(target = "[x86|x86_64]+avx512f")]
+    fn sum_inner(&self) -> f32 {
+        self.data.iter().sum()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Counter(u32);
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "x86_64+sse2")]
+impl Counter {
+    fn get(&self) -> u32 {
+        self.0
+    }
+
+    fn increment(&mut self) {
+        self.0 += 1;
+    }
+
+    fn doubled(self) -> Self {
+        Self(self.0 * 2)
+    }
+}
+
+struct Wrapper<T>(Vec<T>);
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx")]
+impl<T: Copy + std::ops::AddAssign> Wrapper<T> {
+    fn double(&mut self) {
+        for v in self.0.iter_mut() {
+            *v += *v;
+        }
+    }
+}
+
+#[test]
+fn impl_block() {
+    let mut buffer = Buffer::new(vec![0.0, 1.0, 2.0]);
+    buffer.scale_and_add(1.0);
+    assert_eq!(buffer.data, vec![1.0, 3.0, 5.0]);
+    assert_eq!(buffer.sum(), 9.0);
+}
+
+#[test]
+fn generic_impl_block() {
+    let mut wrapper = Wrapper(vec![1u32, 2, 3]);
+    wrapper.double();
+    assert_eq!(wrapper.0, vec![2, 4, 6]);
+}
+
+#[test]
+fn impl_block_receivers() {
+    let mut counter = Counter(1);
+    counter.increment();
+    assert_eq!(counter.get(), 2);
+    assert_eq!(counter.doubled(), Counter(4));
+    assert_eq!(counter.doubled().get(), 4);
+    let selected = Counter::get_selected_version();
+    assert_eq!(Counter::get_versions()[selected.index()], selected);
+    assert_eq!(
+        Counter::doubled_selected_version().target(),
+        selected.target()
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn impl_block_receivers_testing() {
+    multiversion::testing::for_each_version(Counter::get_versions(), |version| {
+        assert_eq!(Counter::get_selected_version(), *version);
+        assert_eq!(Counter(3).get(), 3);
+    });
+}
@@ -0,0 +1,79 @@
+use multiversion::{multiversion, target};
+
+#[multiversion]
+#[clone(target = "x86_64-v4")]
+#[clone(target = "x86_64-v3")]
+#[clone(target = "x86_64-v2")]
+fn add(a: &mut [f32], b: &[f32]) {
+    a.iter_mut().zip(b.iter()).for_each(|(a, b)| *a += b);
+}
+
+#[multiversion]
+#[clone(target = "x86_64-v3")]
+#[clone(target = "x86-64-v2+aes")]
+fn level_name() -> u32 {
+    #[target_cfg(target = "x86_64-v3")]
+    return 3;
+
+    #[target_cfg(target = "x86_64-v2+aes")]
+    return 2;
+
+    #[target_cfg(not(any(target = "x86_64-v3", target = "x86_64-v2+aes")))]
+    return 1;
+}
+
+#[target("x86_64-v3")]
+unsafe fn level_name_v3() -> u32 {
+    level_name_x86_64_v3_version()
+}
+
+#[multiversion]
+#[specialize(target = "x86_64-v3", fn = "level_name_v3", unsafe = true)]
+fn specialized_level_name() -> u32 {
+    1
+}
+
+#[test]
+fn levels() {
+    let mut a = vec![0f32, 2f32, 4f32];
+    add(&mut a, &[1f32, 1f32, 1f32]);
+    assert_eq!(a, vec![1f32, 3f32, 5f32]);
+}
+
+#[test]
+fn level_target_cfg() {
+    let versions = level_name_versions();
+    let expected = if cfg!(target_arch = "x86_64") && versions[0].is_supported() {
+        3
+    } else if cfg!(target_arch = "x86_64") && versions[1].is_supported() {
+        2
+    } else {
+        1
+    };
+    assert_eq!(level_name(), expected);
+    assert_eq!(level_name_default_version(), 1);
+    assert_eq!(specialized_level_name(), if expected == 3 { 3 } else { 1 });
+}
+
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+#[test]
+fn level_selection() {
+    use multiversion::testing::with_cpu;
+
+    let selected = || (level_name(), specialized_level_name());
+    if let Ok(selected) = with_cpu("nehalem", selected) {
+        assert_eq!(selected, (1, 1));
+    }
+    if let Ok(selected) = with_cpu("westmere", selected) {
+        // The version after one with its features enabled during compilation can't be selected
+        let expected = if cfg!(target_feature = "avx2") {
+            (1, 1)
+        } else {
+            (2, 1)
+        };
+        assert_eq!(selected, expected);
+    }
+    if let Ok(selected) = with_cpu("haswell", selected) {
+        assert_eq!(selected, (3, 3));
+    }
+}
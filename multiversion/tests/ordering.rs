@@ -0,0 +1,41 @@
+use multiversion::multiversion;
+
+// Targets are selected in the order they are specified, so AVX2 is specified before SSE4.1
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "aarch64+neon")]
+#[clone(target = "[x86|x86_64]+sse4.1")]
+fn version() -> &'static str {
+    #[target_cfg(target = "[x86|x86_64]+avx2")]
+    return "avx2";
+
+    #[target_cfg(target = "[x86|x86_64]+sse4.1")]
+    return "sse4.1";
+
+    #[target_cfg(not(any(target = "[x86|x86_64]+avx2", target = "[x86|x86_64]+sse4.1")))]
+    return "other";
+}
+
+#[test]
+fn specified_order() {
+    let targets = version_versions()
+        .iter()
+        .map(|version| version.target())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        targets,
+        [
+            "[x86|x86_64]+avx2",
+            "aarch64+neon",
+            "[x86|x86_64]+sse4.1",
+            "default"
+        ]
+    );
+
+    if version_versions()[0].is_supported() {
+        assert_eq!(version(), "avx2");
+        assert_eq!(version_selected_version().target(), "[x86|x86_64]+avx2");
+    } else if version_versions()[2].is_supported() {
+        assert_eq!(version(), "sse4.1");
+    }
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use common::*;
+use multiversion::{multiversion, runtime};
+
+struct Version;
+
+impl Version {
+    #[multiversion]
+    #[clone(target = "[x86|x86_64]+avx2")]
+    #[clone(target = "[x86|x86_64]+avx")]
+    #[clone(target = "[x86|x86_64]+sse4.1")]
+    fn version(&self) -> usize {
+        dispatch!(version())
+    }
+}
+
+// Versions with features enabled during compilation are selected regardless of disabled features
+#[cfg_attr(target_feature = "avx", ignore)]
+#[test]
+fn runtime() {
+    let detected = detected();
+    assert_eq!(version(), detected);
+    assert_eq!(generic_version::<u8>().1, detected);
+    assert_eq!(Version.version(), detected);
+
+    // Disabling features resets the cached dispatch
+    runtime::disable_features(&["avx2"]);
+    let expected = detected.max(1);
+    assert_eq!(version(), expected);
+    assert_eq!(version_selected_version().index(), expected);
+    assert_eq!(generic_version::<u8>().1, expected);
+    assert_eq!(Version.version(), expected);
+
+    // Implied features are disabled too
+    runtime::disable_features(&["sse4.1"]);
+    assert_eq!(version(), 3);
+    assert_eq!(generic_version::<u8>().1, 3);
+    assert_eq!(Version.version(), 3);
+    assert!(version_selected_version().is_default());
+
+    runtime::enable_all_features();
+    assert_eq!(version(), detected);
+    assert_eq!(generic_version::<u8>().1, detected);
+    assert_eq!(Version.version(), detected);
+
+    // Resetting without changing features selects the same version
+    runtime::reset_dispatchers();
+    assert_eq!(version(), detected);
+}
@@ -0,0 +1,112 @@
+use multiversion::multiversion;
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2+fma")]
+#[clone(target = "x86_64", cpu = "nehalem")]
+#[clone(target = "aarch64+neon")]
+fn square(x: &mut [f32]) {
+    for v in x {
+        *v *= *v
+    }
+}
+
+#[multiversion]
+#[clone(target = "x86_64")]
+fn featureless() {}
+
+struct Squarer;
+
+impl Squarer {
+    #[multiversion]
+    #[clone(target = "[x86|x86_64]+avx")]
+    fn square(&self, x: &mut [f32]) {
+        for v in x {
+            *v *= *v
+        }
+    }
+}
+
+#[test]
+fn selected_version() {
+    let mut x = vec![2f32];
+    square(&mut x);
+    assert_eq!(x, vec![4f32]);
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    let avx2 = multiversion::are_cpu_features_detected!("avx2", "fma");
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    let avx2 = false;
+
+    let version = square_selected_version();
+    if avx2 {
+        assert_eq!(version.index(), 0);
+        assert_eq!(version.target(), "[x86|x86_64]+avx2+fma");
+        assert_eq!(version.to_string(), "[x86|x86_64]+avx2+fma");
+        assert!(version.features().contains(&"avx"));
+        assert!(!version.is_default());
+    } else if version.index() == 1 {
+        assert_eq!(version.target(), "x86_64");
+        assert_eq!(version.cpu(), Some("nehalem"));
+        assert_eq!(version.to_string(), "x86_64 (cpu = nehalem)");
+        assert!(version.features().contains(&"sse4.2"));
+    } else if version.index() == 3 {
+        assert_eq!(version.target(), "default");
+        assert_eq!(version.cpu(), None);
+        assert!(version.is_default());
+    } else {
+        assert_eq!(version.index(), 2);
+        assert_eq!(version.target(), "aarch64+neon");
+    }
+}
+
+#[test]
+fn selected_version_featureless() {
+    featureless();
+    let version = featureless_selected_version();
+    if cfg!(target_arch = "x86_64") {
+        assert_eq!(version.index(), 0);
+        assert_eq!(version.target(), "x86_64");
+        assert!(!version.is_default());
+    } else {
+        assert_eq!(version.index(), 1);
+        assert_eq!(version.target(), "default");
+        assert!(version.is_default());
+    }
+    assert!(version.features().is_empty());
+}
+
+#[test]
+fn selected_version_associated() {
+    let version = Squarer::square_selected_version();
+    assert!(version.index() <= 1);
+    let mut x = vec![2f32];
+    Squarer.square(&mut x);
+    assert_eq!(x, vec![4f32]);
+}
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "[x86|x86_64]+sse4.1")]
+#[clone(target = "aarch64+neon")]
+fn called<T>(_: T) -> &'static str {
+    #[target_cfg(target = "[x86|x86_64]+avx2")]
+    return "[x86|x86_64]+avx2";
+
+    #[target_cfg(target = "[x86|x86_64]+sse4.1")]
+    return "[x86|x86_64]+sse4.1";
+
+    #[target_cfg(target = "aarch64+neon")]
+    return "aarch64+neon";
+
+    #[target_cfg(not(any(
+        target = "[x86|x86_64]+avx2",
+        target = "[x86|x86_64]+sse4.1",
+        target = "aarch64+neon"
+    )))]
+    return "default";
+}
+
+#[test]
+fn selected_version_called() {
+    assert_eq!(called(0u8), called_selected_version().target());
+}
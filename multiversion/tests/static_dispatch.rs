@@ -70,6 +70,7 @@ fn static_dispatch_indirect() {
     assert_eq!(x, vec![0f32, 1f32, 4f32, 9f32]);
 }
 
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 #[test]
 fn static_dispatch_target() {
     #[multiversion::target("[x86|x86_64]+avx")]
@@ -101,3 +102,127 @@ fn static_dispatch_associated_indirect() {
     squarer.square_indirect(x.as_mut_slice());
     assert_eq!(x, vec![0f32, 1f32, 4f32, 9f32]);
 }
+
+#[multiversion::multiversion]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "x86_64-v2")]
+fn add_one(x: f32) -> f32 {
+    x + 1.
+}
+
+#[multiversion::multiversion]
+#[clone(target = "[x86|x86_64]+avx+avx2")]
+#[clone(target = "x86_64+cmpxchg16b+popcnt+sse4.2")]
+fn square_plus_one(x: &mut [f32]) {
+    for v in x {
+        *v = dispatch!(add_one(*v * *v));
+    }
+}
+
+#[test]
+fn static_dispatch_implied_features() {
+    let mut x = vec![0f32, 1f32, 2f32, 3f32];
+    square_plus_one(x.as_mut_slice());
+    assert_eq!(x, vec![1f32, 2f32, 5f32, 10f32]);
+}
+
+#[multiversion::multiversion]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "x86_64+sse4.1")]
+fn version() -> u32 {
+    #[target_cfg(target = "[x86|x86_64]+avx")]
+    return 2;
+
+    #[target_cfg(target = "x86_64+sse4.1")]
+    return 1;
+
+    #[target_cfg(not(any(target = "[x86|x86_64]+avx", target = "x86_64+sse4.1")))]
+    return 0;
+}
+
+#[multiversion::multiversion]
+#[clone(target = "x86_64-v3")]
+#[clone(target = "x86_64+sse4.2")]
+#[clone(target = "x86_64+sse3")]
+fn caller_version() -> u32 {
+    dispatch!(version())
+}
+
+struct Versioned;
+
+impl Versioned {
+    #[multiversion::multiversion]
+    #[clone(target = "[x86|x86_64]+avx")]
+    fn version<T: From<u8>>(&self) -> T {
+        #[target_cfg(target = "[x86|x86_64]+avx")]
+        return T::from(1);
+
+        #[target_cfg(not(target = "[x86|x86_64]+avx"))]
+        return T::from(0);
+    }
+
+    #[multiversion::multiversion]
+    #[clone(target = "x86_64+avx2+fma")]
+    fn caller_version(&self) -> u32 {
+        dispatch!(self.version::<u32>()) + dispatch!(Self::version::<u32>(self))
+    }
+}
+
+// Features enabled during compilation, such as with `-C target-cpu`, are enabled in every version
+fn at_least_enabled(version: u32) -> u32 {
+    let enabled = if cfg!(target_feature = "avx") {
+        2
+    } else if cfg!(all(target_arch = "x86_64", target_feature = "sse4.1")) {
+        1
+    } else {
+        0
+    };
+    version.max(enabled)
+}
+
+#[test]
+fn static_dispatch_superset() {
+    assert_eq!(caller_version_default_version(), at_least_enabled(0));
+    #[cfg(target_arch = "x86_64")]
+    {
+        if multiversion::are_cpu_features_detected!("sse3") {
+            assert_eq!(
+                unsafe { caller_version_sse3_version() },
+                at_least_enabled(0)
+            );
+        }
+        if multiversion::are_cpu_features_detected!("sse4.2") {
+            assert_eq!(
+                unsafe { caller_version_sse42_version() },
+                at_least_enabled(1)
+            );
+        }
+        if multiversion::are_cpu_features_detected!(
+            "avx2",
+            "bmi1",
+            "bmi2",
+            "cmpxchg16b",
+            "f16c",
+            "fma",
+            "lzcnt",
+            "movbe",
+            "popcnt",
+            "xsave"
+        ) {
+            assert_eq!(unsafe { caller_version_x86_64_v3_version() }, 2);
+        }
+    }
+}
+
+#[test]
+fn static_dispatch_superset_associated() {
+    let expected = if cfg!(target_feature = "avx") { 2 } else { 0 };
+    assert_eq!(Versioned.caller_version_default_version(), expected);
+    #[cfg(target_arch = "x86_64")]
+    {
+        if multiversion::are_cpu_features_detected!("avx2", "fma") {
+            assert_eq!(unsafe { Versioned.caller_version_avx2_fma_version() }, 2);
+            assert_eq!(Versioned.caller_version(), 2);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use multiversion::multiversion;
+
+// SSE2 is enabled on every x86-64 target, so the SSE2 version is always selected there and the
+// later versions can't be selected, but are still compiled
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "x86_64+sse2")]
+#[clone(target = "[x86|x86_64]+sse")]
+fn version() -> &'static str {
+    #[target_cfg(target = "[x86|x86_64]+avx2")]
+    return "avx2";
+
+    #[target_cfg(target = "x86_64+sse2")]
+    return "sse2";
+
+    #[target_cfg(target = "[x86|x86_64]+sse")]
+    return "sse";
+
+    #[target_cfg(not(any(
+        target = "[x86|x86_64]+avx2",
+        target = "x86_64+sse2",
+        target = "[x86|x86_64]+sse"
+    )))]
+    return "default";
+}
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "x86_64+sse2")]
+fn generic_version<T: Default>() -> (T, &'static str) {
+    (T::default(), dispatch!(version()))
+}
+
+#[test]
+fn enabled_features() {
+    let versions = version_versions();
+    if cfg!(target_arch = "x86_64") {
+        assert!(["avx2", "sse2"].contains(&version()));
+        assert!(["avx2", "sse2"].contains(&generic_version::<u8>().1));
+        assert!(version_selected_version().index() <= 1);
+        let expected = if cfg!(target_feature = "avx2") { 1 } else { 2 };
+        assert!(versions[expected - 1].is_supported());
+        assert!(!versions[2].is_supported());
+        // The default version is still compiled, even though it's never selected
+        assert!(versions[3].is_supported());
+        assert_eq!(VERSION_VERSIONS.len(), 4);
+        assert!(VERSION_VERSIONS.last().unwrap().0.is_default());
+        let names = ["avx2", "sse2", "sse", "default"];
+        for (index, ((version, f), expected)) in VERSION_VERSIONS.iter().zip(names).enumerate() {
+            assert_eq!(version.index(), index);
+            // Every version but AVX2 runs on any x86-64 CPU
+            if index > 0 || version.is_supported() {
+                assert_eq!(unsafe { f() }, expected);
+            }
+        }
+    } else if cfg!(not(target_arch = "x86")) {
+        assert_eq!(version(), "default");
+        assert!(versions[3].is_supported());
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn enabled_features_ignore_disabled() {
+    if cfg!(target_arch = "x86_64") {
+        let expected = if cfg!(target_feature = "avx2") {
+            "avx2"
+        } else {
+            "sse2"
+        };
+        multiversion::runtime::disable_features(&["sse2"]);
+        assert_eq!(version(), expected);
+        assert_eq!(generic_version::<u8>().1, expected);
+        multiversion::runtime::enable_all_features();
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn enabled_features_testing() {
+    let mut tested = Vec::new();
+    multiversion::testing::for_each_version(version_versions(), |v| {
+        assert_eq!(version_selected_version(), *v);
+        tested.push(version());
+    });
+    if cfg!(target_arch = "x86_64") {
+        assert_eq!(tested.last(), Some(&"default"));
+    }
+}
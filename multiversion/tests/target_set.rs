@@ -0,0 +1,58 @@
+use multiversion::{multiversion, target_set};
+
+target_set!(SIMD = ["x86_64-v3", "[x86|x86_64]+sse4.1", "aarch64+neon"]);
+
+mod sets {
+    multiversion::target_set!(pub(crate) AVX = ["[x86|x86_64]+avx2+fma", "[x86|x86_64]+avx",]);
+}
+
+#[multiversion(targets = SIMD)]
+fn square(x: &mut [f32]) {
+    for v in x {
+        *v *= *v
+    }
+}
+
+#[multiversion(targets = SIMD)]
+#[clone(target = "x86+sse")]
+fn square_plus_one(x: &mut [f32]) {
+    dispatch!(square(x));
+    for v in x {
+        *v += 1.0;
+    }
+}
+
+#[multiversion(targets = crate::sets::AVX)]
+fn version() -> u32 {
+    #[target_cfg(target = "[x86|x86_64]+avx2+fma")]
+    return 2;
+
+    #[target_cfg(target = "[x86|x86_64]+avx")]
+    return 1;
+
+    #[target_cfg(not(any(target = "[x86|x86_64]+avx2+fma", target = "[x86|x86_64]+avx")))]
+    return 0;
+}
+
+struct Buffer(Vec<f32>);
+
+#[multiversion(targets = SIMD)]
+impl Buffer {
+    fn square(&mut self) {
+        dispatch!(square(&mut self.0));
+    }
+}
+
+#[test]
+fn target_set() {
+    let mut x = vec![0f32, 1f32, 2f32];
+    square_plus_one(&mut x);
+    assert_eq!(x, vec![1f32, 2f32, 5f32]);
+
+    let mut buffer = Buffer(x);
+    buffer.square();
+    assert_eq!(buffer.0, vec![1f32, 4f32, 25f32]);
+
+    assert_eq!(version_default_version(), 0);
+    assert!(version() <= 2);
+}
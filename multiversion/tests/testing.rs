@@ -0,0 +1,207 @@
+#![cfg(feature = "std")]
+
+use multiversion::multiversion;
+use multiversion::testing::{self, UnsupportedCpu};
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "[x86|x86_64]+sse4.1")]
+#[clone(target = "aarch64+neon")]
+fn version() -> usize {
+    #[target_cfg(target = "[x86|x86_64]+avx2")]
+    return 0;
+
+    #[target_cfg(target = "[x86|x86_64]+avx")]
+    return 1;
+
+    #[target_cfg(target = "[x86|x86_64]+sse4.1")]
+    return 2;
+
+    #[target_cfg(target = "aarch64+neon")]
+    return 3;
+
+    #[target_cfg(not(any(
+        target = "[x86|x86_64]+avx2",
+        target = "[x86|x86_64]+avx",
+        target = "[x86|x86_64]+sse4.1",
+        target = "aarch64+neon"
+    )))]
+    return 4;
+}
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2")]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "[x86|x86_64]+sse4.1")]
+#[clone(target = "aarch64+neon")]
+fn generic_version<T: Default>() -> (T, usize) {
+    (T::default(), dispatch!(version()))
+}
+
+struct Version;
+
+impl Version {
+    #[multiversion]
+    #[clone(target = "[x86|x86_64]+avx2")]
+    #[clone(target = "[x86|x86_64]+avx")]
+    #[clone(target = "[x86|x86_64]+sse4.1")]
+    #[clone(target = "aarch64+neon")]
+    fn version(&self) -> usize {
+        dispatch!(version())
+    }
+}
+
+// Features enabled during compilation, such as with `-C target-cpu`, are enabled in every version,
+// so static dispatch selects at least the first version with enabled features
+fn statically_dispatched(index: usize) -> usize {
+    let x86 = cfg!(any(target_arch = "x86", target_arch = "x86_64"));
+    let enabled = if x86 && cfg!(target_feature = "avx2") {
+        0
+    } else if x86 && cfg!(target_feature = "avx") {
+        1
+    } else if x86 && cfg!(target_feature = "sse4.1") {
+        2
+    } else if cfg!(all(target_arch = "aarch64", target_feature = "neon")) {
+        3
+    } else {
+        4
+    };
+    index.min(enabled)
+}
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "x86+sse")]
+fn sum(x: &[f32]) -> f32 {
+    x.iter().sum()
+}
+
+#[multiversion]
+#[clone(target = "x86_64+sse2")]
+fn disagree() -> bool {
+    #[target_cfg(target = "x86_64+sse2")]
+    return true;
+
+    #[target_cfg(not(target = "x86_64+sse2"))]
+    return false;
+}
+
+#[multiversion]
+#[clone(target = "x86_64+sse2")]
+#[clone(target = "x86_64")]
+fn disagree_featureless() -> bool {
+    #[target_cfg(target = "x86_64+sse2")]
+    return true;
+
+    #[target_cfg(not(target = "x86_64+sse2"))]
+    return false;
+}
+
+#[test]
+fn versions() {
+    let versions = version_versions();
+    assert_eq!(versions.len(), 5);
+    for (index, version) in versions.iter().enumerate() {
+        assert_eq!(version.index(), index);
+    }
+    assert_eq!(versions[1].target(), "[x86|x86_64]+avx");
+    assert!(versions[4].is_default());
+    assert!(versions[4].is_supported());
+    assert_eq!(
+        versions[version_selected_version().index()],
+        version_selected_version()
+    );
+}
+
+#[test]
+fn for_each_version() {
+    let mut tested = Vec::new();
+    testing::for_each_version(version_versions(), |v| {
+        assert_eq!(version(), v.index());
+        assert_eq!(version_selected_version(), *v);
+        assert_eq!(generic_version::<u8>().1, statically_dispatched(v.index()));
+        assert_eq!(Version.version(), statically_dispatched(v.index()));
+        tested.push(v.index());
+    });
+    let supported = version_versions()
+        .iter()
+        .filter(|v| v.is_supported())
+        .map(|v| v.index())
+        .collect::<Vec<_>>();
+    assert_eq!(tested, supported);
+
+    // The dispatcher is unaffected afterwards
+    assert_eq!(version(), supported[0]);
+    assert_eq!(Version.version(), statically_dispatched(supported[0]));
+}
+
+#[test]
+fn assert_versions_agree() {
+    testing::assert_versions_agree(sum_versions(), || sum(&[1., 2., 3.]));
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+#[should_panic(expected = "version `x86_64+sse2` produced true")]
+fn assert_versions_disagree() {
+    testing::assert_versions_agree(disagree_versions(), disagree);
+}
+
+// The version without features replaces the default version, so is the baseline
+#[cfg(target_arch = "x86_64")]
+#[test]
+#[should_panic(
+    expected = "version `x86_64+sse2` produced true, but baseline version `x86_64` produced false"
+)]
+fn assert_versions_disagree_featureless() {
+    testing::assert_versions_agree(disagree_featureless_versions(), disagree_featureless);
+}
+
+#[test]
+#[should_panic(expected = "no baseline version available")]
+fn assert_versions_agree_no_baseline() {
+    testing::assert_versions_agree(&[], || ());
+}
+
+#[test]
+fn with_cpu() {
+    let native = version();
+    let selected = |expected| {
+        assert_eq!(version(), expected);
+        assert_eq!(version_selected_version().index(), expected);
+        assert_eq!(generic_version::<u8>().1, statically_dispatched(expected));
+        assert_eq!(Version.version(), statically_dispatched(expected));
+    };
+
+    if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+        // The SSE4.1 version can't be selected if AVX is enabled during compilation
+        let sse41 = if cfg!(target_feature = "avx") { 4 } else { 2 };
+        if let Ok(()) = testing::with_cpu("nehalem", || selected(sse41)) {
+            assert_eq!(testing::with_cpu("westmere", || selected(sse41)), Ok(()));
+        }
+        if let Err(UnsupportedCpu::MissingFeatures(missing)) =
+            testing::with_cpu("haswell", || selected(0))
+        {
+            assert!(!missing.is_empty());
+        }
+        assert_eq!(
+            testing::with_cpu("neoverse-n1", || ()),
+            Err(UnsupportedCpu::OtherArchitecture)
+        );
+    } else if cfg!(target_arch = "aarch64") {
+        if let Ok(()) = testing::with_cpu("neoverse-n1", || selected(3)) {
+            assert_eq!(
+                testing::with_cpu("haswell", || ()),
+                Err(UnsupportedCpu::OtherArchitecture)
+            );
+        }
+    }
+    assert_eq!(
+        testing::with_cpu("pentium", || ()),
+        Err(UnsupportedCpu::Unknown)
+    );
+
+    // The dispatcher is unaffected afterwards
+    assert_eq!(version(), native);
+}
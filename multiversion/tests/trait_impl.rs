@@ -0,0 +1,176 @@
+use multiversion::multiversion;
+
+trait Transform {
+    fn scale(&mut self, x: f32);
+    fn offset(&mut self, x: f32);
+    fn scale_and_offset(&mut self, x: f32, y: f32);
+    fn len(&self) -> usize;
+}
+
+struct Buffer(Vec<f32>);
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2+fma")]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "x86+sse")]
+impl Transform for Buffer {
+    fn scale(&mut self, x: f32) {
+        for v in self.0.iter_mut() {
+            *v *= x;
+        }
+    }
+
+    fn offset(&mut self, x: f32) {
+        for v in self.0.iter_mut() {
+            *v += x;
+        }
+    }
+
+    fn scale_and_offset(&mut self, x: f32, y: f32) {
+        dispatch!(self.scale(x));
+        dispatch!(Self::offset(self, y));
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+trait Sum<T> {
+    fn sum(&self) -> T;
+    fn count(&self) -> usize;
+}
+
+struct Wrapper<T>(Vec<T>);
+
+#[multiversion]
+impl<T: Copy + Default + std::ops::AddAssign> Sum<T> for Wrapper<T> {
+    #[multiversion]
+    #[clone(target = "[x86|x86_64]+avx")]
+    #[clone(target = "[arm|aarch64]+neon")]
+    fn sum(&self) -> T {
+        let mut sum = T::default();
+        for v in &self.0 {
+            sum += *v;
+        }
+        sum
+    }
+
+    fn count(&self) -> usize {
+        self.0.len()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Vector([f32; 4]);
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "[arm|aarch64]+neon")]
+impl std::ops::Add for Vector {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self::Output {
+        let mut out = self;
+        for (a, b) in out.0.iter_mut().zip(other.0.iter()) {
+            *a += b;
+        }
+        out
+    }
+}
+
+// Foreign types can implement local traits
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "[arm|aarch64]+neon")]
+impl Transform for Vec<f32> {
+    fn scale(&mut self, x: f32) {
+        for v in self.iter_mut() {
+            *v *= x;
+        }
+    }
+
+    fn offset(&mut self, x: f32) {
+        for v in self.iter_mut() {
+            *v += x;
+        }
+    }
+
+    fn scale_and_offset(&mut self, x: f32, y: f32) {
+        dispatch!(self.scale(x));
+        dispatch!(Self::offset(self, y));
+    }
+
+    fn len(&self) -> usize {
+        <[f32]>::len(self)
+    }
+}
+
+// Methods with the same name in different implementations
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "[arm|aarch64]+neon")]
+impl std::ops::Mul<f32> for Vector {
+    type Output = Self;
+
+    fn mul(mut self, other: f32) -> Self::Output {
+        for a in self.0.iter_mut() {
+            *a *= other;
+        }
+        self
+    }
+}
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "[arm|aarch64]+neon")]
+impl std::ops::Mul<Vector> for Vector {
+    type Output = Self;
+
+    fn mul(mut self, other: Vector) -> Self::Output {
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a *= b;
+        }
+        self
+    }
+}
+
+#[test]
+fn trait_impl() {
+    let mut buffer = Buffer(vec![0.0, 1.0, 2.0]);
+    buffer.scale_and_offset(2.0, 1.0);
+    assert_eq!(buffer.0, vec![1.0, 3.0, 5.0]);
+    buffer.scale(2.0);
+    buffer.offset(-1.0);
+    assert_eq!(buffer.0, vec![1.0, 5.0, 9.0]);
+    assert_eq!(buffer.len(), 3);
+}
+
+#[test]
+fn generic_trait_impl() {
+    let wrapper = Wrapper(vec![1u32, 2, 3]);
+    assert_eq!(wrapper.sum(), 6);
+    assert_eq!(wrapper.sum_default_version(), 6);
+    assert_eq!(wrapper.count(), 3);
+}
+
+#[test]
+fn associated_type() {
+    let a = Vector([1.0, 2.0, 3.0, 4.0]);
+    assert_eq!(a + a, Vector([2.0, 4.0, 6.0, 8.0]));
+}
+
+#[test]
+fn foreign_type() {
+    let mut v = vec![0.0, 1.0, 2.0];
+    v.scale_and_offset(2.0, 1.0);
+    assert_eq!(v, vec![1.0, 3.0, 5.0]);
+    assert_eq!(Transform::len(&v), 3);
+}
+
+#[test]
+fn same_method_name() {
+    let a = Vector([1.0, 2.0, 3.0, 4.0]);
+    assert_eq!(a * 2.0, Vector([2.0, 4.0, 6.0, 8.0]));
+    assert_eq!(a * a, Vector([1.0, 4.0, 9.0, 16.0]));
+}
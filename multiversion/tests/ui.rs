@@ -0,0 +1,7 @@
+// Compiler diagnostics change between releases, so the expected output is only checked on stable
+#[rustversion::attr(not(stable), ignore)]
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
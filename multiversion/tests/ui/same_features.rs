@@ -0,0 +1,9 @@
+use multiversion::multiversion;
+
+#[multiversion]
+#[clone(target = "x86+avx")]
+#[clone(target = "x86_64+avx")]
+#[clone(target = "[x86|x86_64]+avx")]
+fn version() {}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use multiversion::multiversion;
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+sse4.1")]
+#[clone(target = "aarch64+neon")]
+#[clone(target = "[x86|x86_64]+avx2")]
+fn version() {}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use multiversion::dispatch_region;
+
+fn main() {
+    dispatch_region! {
+        #[clone(target = "x86_64+avx")]
+        #[clone(target = "[x86|x86_64]+avx2+fma")]
+        {}
+    }
+}
@@ -0,0 +1,11 @@
+use multiversion::multiversion;
+
+#[multiversion]
+#[clone(target = "aarch64+neon+lor")]
+fn version() {}
+
+// Features that can't be detected may still be enabled
+#[multiversion::target("aarch64+neon+lor")]
+unsafe fn enabled() {}
+
+fn main() {}
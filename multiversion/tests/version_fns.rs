@@ -0,0 +1,60 @@
+use multiversion::multiversion;
+
+#[multiversion]
+#[clone(target = "[x86|x86_64]+avx2+fma")]
+#[clone(target = "[x86|x86_64]+avx")]
+#[clone(target = "aarch64+neon")]
+fn square(x: &mut [f32]) {
+    for v in x {
+        *v *= *v
+    }
+}
+
+#[multiversion]
+#[clone(target = "x86_64+avx")]
+#[clone(target = "x86_64")]
+fn version<'a>(x: &'a str, y: &str) -> (&'a str, &'static str) {
+    assert!(!y.is_empty());
+
+    #[target_cfg(target = "x86_64+avx")]
+    return (x, "avx");
+
+    #[target_cfg(not(target = "x86_64+avx"))]
+    return (x, "default");
+}
+
+#[test]
+fn version_fns() {
+    for (version, f) in SQUARE_VERSIONS {
+        assert_eq!(*version, square_versions()[version.index()]);
+        if version.is_supported() {
+            let mut x = vec![2f32, 3f32];
+            unsafe { f(&mut x) };
+            assert_eq!(x, vec![4f32, 9f32]);
+        }
+    }
+
+    let arch_versions = if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+        3
+    } else if cfg!(target_arch = "aarch64") {
+        2
+    } else {
+        1
+    };
+    assert_eq!(SQUARE_VERSIONS.len(), arch_versions);
+    assert!(SQUARE_VERSIONS.last().unwrap().0.is_default());
+}
+
+#[test]
+fn featureless() {
+    let (version, f) = VERSION_VERSIONS.last().unwrap();
+    assert!(version.is_supported());
+    assert_eq!(unsafe { f("x", "y") }, ("x", "default"));
+    if cfg!(target_arch = "x86_64") {
+        assert_eq!(VERSION_VERSIONS.len(), 2);
+        assert_eq!(version.target(), "x86_64");
+    } else {
+        assert_eq!(VERSION_VERSIONS.len(), 1);
+        assert!(version.is_default());
+    }
+}